@@ -0,0 +1,118 @@
+use crate::error::Error;
+use crate::reflog;
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+
+const BACKUP_PREFIX: &str = "refs/git-rewrite/backup/";
+
+/// A backup of a ref, taken before a rewrite moved it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupInfo {
+    /// Full name of the backup ref
+    pub name: String,
+    /// Ref the backup was taken from (`refs/heads/main`, or `HEAD` when detached)
+    pub source: String,
+    /// Seconds since the epoch
+    pub timestamp: i64,
+    pub hash: String,
+    pub summary: String,
+}
+
+/// Backup ref name for `ref_name`: `refs/git-rewrite/backup/heads/main/<timestamp>`.
+fn backup_name(ref_name: &str, timestamp: i64) -> String {
+    format!("{}{}/{}", BACKUP_PREFIX, ref_name.trim_start_matches("refs/"), timestamp)
+}
+
+/// Source ref and timestamp of a backup ref name.
+fn parse_backup_name(name: &str) -> Option<(String, i64)> {
+    let rest = name.strip_prefix(BACKUP_PREFIX)?;
+    let (source, stamp) = rest.rsplit_once('/')?;
+    // Backups taken within the same second get a `-<n>` suffix
+    let timestamp = stamp.split('-').next()?.parse().ok()?;
+    let source = if source == "HEAD" { source.to_string() } else { format!("refs/{}", source) };
+    Some((source, timestamp))
+}
+
+/// Keep `target`, the previous target of `ref_name`, under a backup ref.
+pub(crate) fn create(repo: &Repository, ref_name: &str, target: Oid, timestamp: i64) -> Result<String, Error> {
+    let base = backup_name(ref_name, timestamp);
+    let mut name = base.clone();
+    let mut suffix = 1;
+    while repo.find_reference(&name).is_ok() {
+        name = format!("{}-{}", base, suffix);
+        suffix += 1;
+    }
+
+    repo.reference(&name, target, false, "git-rewrite: backup")
+        .map_err(|e| Error::git(&format!("Failed to create backup of {}", ref_name), e))?;
+    Ok(name)
+}
+
+/// Delete the backup ref `name`.
+pub(crate) fn delete(repo: &Repository, name: &str) -> Result<(), Error> {
+    repo.find_reference(name)
+        .and_then(|mut reference| reference.delete())
+        .map_err(|e| Error::git(&format!("Failed to delete backup {}", name), e))
+}
+
+/// Every backup, newest first.
+pub(crate) fn list(repo: &Repository) -> Result<Vec<BackupInfo>, Error> {
+    let references = repo.references_glob(&format!("{}*", BACKUP_PREFIX))
+        .map_err(|e| Error::git("Failed to list backups", e))?;
+
+    let mut backups = Vec::new();
+    for reference in references {
+        let reference = reference.map_err(|e| Error::git("Failed to read backup", e))?;
+        let Some(name) = reference.name() else { continue };
+        let Some((source, timestamp)) = parse_backup_name(name) else { continue };
+        let Ok(commit) = reference.peel_to_commit() else { continue };
+
+        backups.push(BackupInfo {
+            name: name.to_string(),
+            source,
+            timestamp,
+            hash: commit.id().to_string(),
+            summary: commit.summary().unwrap_or("").to_string(),
+        });
+    }
+
+    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| b.name.cmp(&a.name)));
+    Ok(backups)
+}
+
+/// Point the ref a backup was taken from back at the backed up commit.
+/// Returns the restored ref.
+pub(crate) fn restore(repo: &Repository, name: &str) -> Result<String, Error> {
+    let (source, _) = parse_backup_name(name).ok_or_else(|| Error::invalid(format!("{} is not a backup", name)))?;
+    let target = repo.find_reference(name)
+        .map_err(|e| Error::git("Failed to find backup", e))?
+        .target()
+        .ok_or_else(|| Error::invalid("Backup has no target"))?;
+
+    let log_message = reflog::message(&format!("restore backup {}", name.trim_start_matches(BACKUP_PREFIX)));
+    repo.reference(&source, target, true, &log_message)
+        .map_err(|e| Error::git(&format!("Failed to restore {}", source), e))?;
+
+    Ok(source)
+}
+
+/// Delete backups taken more than `max_age_days` days before `now`.
+/// Returns the names of the deleted backups.
+pub(crate) fn prune(repo: &Repository, max_age_days: u64, now: i64) -> Result<Vec<String>, Error> {
+    // An age too large to count in seconds keeps everything
+    let Some(max_age) = max_age_days.checked_mul(24 * 60 * 60).and_then(|secs| i64::try_from(secs).ok()) else {
+        return Ok(Vec::new());
+    };
+    let cutoff = now.saturating_sub(max_age);
+
+    let mut pruned = Vec::new();
+    for backup in list(repo)? {
+        if backup.timestamp >= cutoff {
+            continue;
+        }
+        delete(repo, &backup.name)?;
+        pruned.push(backup.name);
+    }
+
+    Ok(pruned)
+}
@@ -0,0 +1,42 @@
+use crate::error::Error;
+use crate::progress::Progress;
+use crate::rewriter::Rewriter;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Repositories opened by the read-only commands, kept in Tauri state so
+/// that paging through the history does not reopen the repository and walk
+/// it again on every call.
+#[derive(Default)]
+pub(crate) struct RepoCache {
+    repos: Mutex<HashMap<String, Arc<Mutex<Rewriter>>>>,
+}
+
+impl RepoCache {
+    /// Run `f` on the cached repository at `path`, opening it the first time.
+    /// `progress` is only used for this call.
+    pub(crate) fn with_repo<T>(
+        &self,
+        path: &str,
+        progress: Progress,
+        f: impl FnOnce(&mut Rewriter) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let cached = {
+            let mut repos = self.repos.lock().unwrap_or_else(|e| e.into_inner());
+            match repos.get(path) {
+                Some(cached) => cached.clone(),
+                None => {
+                    let cached = Arc::new(Mutex::new(Rewriter::open(path)?));
+                    repos.insert(path.to_string(), cached.clone());
+                    cached
+                }
+            }
+        };
+        let mut rewriter = cached.lock().unwrap_or_else(|e| e.into_inner());
+        rewriter.set_progress(progress);
+        let result = f(&mut rewriter);
+        // Let go of the cancellation token of this call
+        rewriter.set_progress(Progress::default());
+        result
+    }
+}
@@ -0,0 +1,209 @@
+//! `git-rewrite-cli`: the read and rewrite commands of the app, without the
+//! GUI, printing their result as JSON.
+
+use crate::error::Error;
+use crate::history::RefSelector;
+use crate::repo::resolve_commit;
+use crate::rewriter::{AutoStash, Rewriter};
+use serde::Serialize;
+use std::io::Write;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: git-rewrite-cli [--repo <path>] <command> [arguments]
+
+Commands:
+  get-commits [refs] [--offset <n>] [--limit <n>]
+  search-commits <query> [refs] [--offset <n>] [--limit <n>]
+  get-commit-diff <commit>
+  edit-commit-message <commit> <message> [--auto-stash [--include-untracked]]
+  squash-commits <commit> <commit>... --message <message> [--auto-stash [--include-untracked]]
+
+Options:
+  --repo <path>   Repository to work on, the current directory by default
+
+A <commit> is a full or abbreviated hash, or any revision like HEAD~2.
+
+Refs, HEAD when none is given, can be combined like with `git log`:
+  --head  --branch <name>  --branches  --remotes  --tags  --glob <pattern>  --all
+
+The result is printed to stdout as JSON. Failures are printed to stderr as
+the same JSON error the app gets, with exit code 1; usage errors exit with 2.";
+
+/// Page size of `get-commits` and `search-commits`, like the commit list.
+const DEFAULT_LIMIT: usize = 50;
+
+/// Options taking a value; every other `--` argument is a flag.
+const VALUE_OPTIONS: &[&str] = &["--repo", "--offset", "--limit", "--message", "--branch", "--glob"];
+
+/// Options and flags selecting the refs of `get-commits` and `search-commits`.
+const REF_OPTIONS: &[&str] = &["--branch", "--glob"];
+const REF_FLAGS: &[&str] = &["--head", "--branches", "--remotes", "--tags", "--all"];
+
+/// Command line split into positional arguments, options and flags.
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args { positional: Vec::new(), options: Vec::new(), flags: Vec::new() };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.positional.extend(args.by_ref());
+            } else if let Some((name, value)) = arg.split_once('=').filter(|_| arg.starts_with("--")) {
+                parsed.options.push((name.to_string(), value.to_string()));
+            } else if VALUE_OPTIONS.contains(&arg.as_str()) {
+                let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                parsed.options.push((arg, value));
+            } else if arg.starts_with("--") {
+                parsed.flags.push(arg);
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    fn number(&self, name: &str, default: usize) -> Result<usize, String> {
+        match self.option(name) {
+            Some(value) => value.parse().map_err(|_| format!("{} must be a number, got '{}'", name, value)),
+            None => Ok(default),
+        }
+    }
+
+    /// Refs selected by the ref options and flags.
+    fn refs(&self) -> Vec<RefSelector> {
+        let options = self.options.iter().filter_map(|(name, value)| match name.as_str() {
+            "--branch" => Some(RefSelector::Branch(value.clone())),
+            "--glob" => Some(RefSelector::Glob(value.clone())),
+            _ => None,
+        });
+        let flags = self.flags.iter().flat_map(|flag| match flag.as_str() {
+            "--head" => vec![RefSelector::Head],
+            "--branches" => vec![RefSelector::AllBranches],
+            "--remotes" => vec![RefSelector::AllRemotes],
+            "--tags" => vec![RefSelector::Tags],
+            "--all" => vec![RefSelector::Head, RefSelector::AllBranches, RefSelector::AllRemotes, RefSelector::Tags],
+            _ => vec![],
+        });
+        options.chain(flags).collect()
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    /// Fail on options and flags that `command` does not know.
+    fn check_known(&self, command: &str, options: &[&str], flags: &[&str]) -> Result<(), String> {
+        let unknown = self.options.iter().map(|(name, _)| name)
+            .filter(|name| *name != "--repo" && !options.contains(&name.as_str()))
+            .chain(self.flags.iter().filter(|flag| !flags.contains(&flag.as_str())))
+            .next();
+        match unknown {
+            Some(name) => Err(format!("Unknown option {} for {}", name, command)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What a command ends with: its JSON result, a failure of the command, or a
+/// command line that does not make sense.
+enum Outcome {
+    Done(String),
+    Failed(Error),
+    Usage(String),
+}
+
+fn json(result: Result<impl Serialize, Error>) -> Outcome {
+    match result.and_then(|value| serde_json::to_string_pretty(&value).map_err(|e| Error::io("Failed to serialize the result", e))) {
+        Ok(value) => Outcome::Done(value),
+        Err(e) => Outcome::Failed(e),
+    }
+}
+
+fn auto_stash(args: &Args) -> AutoStash {
+    AutoStash::from_flags(args.flag("--auto-stash"), args.flag("--include-untracked"))
+}
+
+fn execute(args: &Args) -> Result<Outcome, String> {
+    let repo_path = args.option("--repo").unwrap_or(".");
+    let (command, positional) = args.positional.split_first().ok_or("No command given")?;
+
+    let outcome = match (command.as_str(), positional) {
+        ("get-commits", []) => {
+            args.check_known(command, &[&["--offset", "--limit"], REF_OPTIONS].concat(), REF_FLAGS)?;
+            let (offset, limit) = (args.number("--offset", 0)?, args.number("--limit", DEFAULT_LIMIT)?);
+            json(Rewriter::open(repo_path).and_then(|mut rewriter| rewriter.commits(&args.refs(), offset, limit)))
+        }
+        ("search-commits", [query]) => {
+            args.check_known(command, &[&["--offset", "--limit"], REF_OPTIONS].concat(), REF_FLAGS)?;
+            let (offset, limit) = (args.number("--offset", 0)?, args.number("--limit", DEFAULT_LIMIT)?);
+            json(Rewriter::open(repo_path).and_then(|mut rewriter| rewriter.search_commits(&args.refs(), query, offset, limit)))
+        }
+        ("get-commit-diff", [commit]) => {
+            args.check_known(command, &[], &[])?;
+            json(Rewriter::open(repo_path).and_then(|rewriter| {
+                rewriter.commit_diff(resolve_commit(rewriter.repository(), commit)?)
+            }))
+        }
+        ("edit-commit-message", [commit, message]) => {
+            args.check_known(command, &[], &["--auto-stash", "--include-untracked"])?;
+            json(Rewriter::open(repo_path).and_then(|mut rewriter| {
+                let commit = resolve_commit(rewriter.repository(), commit)?;
+                rewriter.edit_message(commit, message, auto_stash(args))
+            }))
+        }
+        ("squash-commits", commits) if !commits.is_empty() => {
+            args.check_known(command, &["--message"], &["--auto-stash", "--include-untracked"])?;
+            let message = args.option("--message").ok_or("squash-commits needs --message")?;
+            json(Rewriter::open(repo_path).and_then(|mut rewriter| {
+                let commits = commits.iter()
+                    .map(|commit| resolve_commit(rewriter.repository(), commit))
+                    .collect::<Result<Vec<_>, _>>()?;
+                rewriter.squash(&commits, message, auto_stash(args))
+            }))
+        }
+        ("get-commits" | "search-commits" | "get-commit-diff" | "edit-commit-message" | "squash-commits", _) => {
+            Outcome::Usage(format!("Wrong number of arguments for {}", command))
+        }
+        _ => Outcome::Usage(format!("Unknown command '{}'", command)),
+    };
+    Ok(outcome)
+}
+
+/// Entry point of `git-rewrite-cli`.
+pub fn run() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let outcome = Args::parse(args)
+        .and_then(|args| execute(&args))
+        .unwrap_or_else(Outcome::Usage);
+
+    match outcome {
+        Outcome::Done(output) => {
+            // A closed pipe (`| head`) is not a failure of the command
+            let _ = writeln!(std::io::stdout(), "{}", output);
+            ExitCode::SUCCESS
+        }
+        Outcome::Failed(error) => {
+            eprintln!("{}", serde_json::to_string_pretty(&error).unwrap_or_else(|_| error.to_string()));
+            ExitCode::FAILURE
+        }
+        Outcome::Usage(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,436 @@
+//! The Tauri commands: thin wrappers parsing the arguments of the frontend
+//! and running the matching [`Rewriter`] method off the invoke thread.
+
+use crate::backup::BackupInfo;
+use crate::cache::RepoCache;
+use crate::diff::CommitDiff;
+use crate::error::{Error, ErrorKind};
+use crate::history::{CommitPage, RefSelector};
+use crate::identity::{IdentityEdit, MailmapResult};
+use crate::oplog::Operation;
+use crate::preview::RewritePreview;
+use crate::progress::{CancelToken, OperationProgress, Progress};
+use crate::rebase::{ConflictEntry, RebaseStep};
+use crate::reflog::ReflogEntry;
+use crate::repo::{BranchInfo, RepoInfo, RepoValidation};
+use crate::rewrite::RewriteResult;
+use crate::rewriter::{AutoStash, Rewriter};
+use crate::split::SplitGroup;
+use git2::Oid;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// Event emitted while a long command goes through commits (or files, for a
+/// diff), with an [`OperationProgress`] payload.
+const PROGRESS_EVENT: &str = "operation-progress";
+
+/// Cancellation tokens of the commands started with an `operation_id`.
+#[derive(Default)]
+pub(crate) struct RunningOperations(Mutex<HashMap<String, CancelToken>>);
+
+/// Progress of the command `operation`, emitted to the frontend. With an
+/// `operation_id`, the command can be stopped with [`cancel_operation`].
+fn progress_events(app: &AppHandle, operation: &'static str, operation_id: Option<String>) -> Progress {
+    let cancel = CancelToken::default();
+    if let Some(id) = &operation_id {
+        let running = app.state::<RunningOperations>();
+        let mut running = running.0.lock().unwrap_or_else(|e| e.into_inner());
+        // Tokens of finished commands are no longer held by anyone else
+        running.retain(|_, token| token.in_use());
+        running.insert(id.clone(), cancel.clone());
+    }
+
+    let app = app.clone();
+    Progress::new(move |done, total| {
+        let _ = app.emit(PROGRESS_EVENT, OperationProgress {
+            operation: operation.to_string(),
+            operation_id: operation_id.clone(),
+            done,
+            total,
+        });
+    }).with_cancel(cancel)
+}
+
+/// Cancel the running command started with `operation_id`. Refs are left
+/// untouched by a cancelled rewrite. Returns `false` when no such command is
+/// running.
+#[tauri::command]
+pub(crate) fn cancel_operation(running: State<'_, RunningOperations>, operation_id: String) -> Result<bool, Error> {
+    let running = running.0.lock().unwrap_or_else(|e| e.into_inner());
+    match running.get(&operation_id).filter(|token| token.in_use()) {
+        Some(token) => {
+            token.cancel();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Held while a command moves refs, so that a command started meanwhile
+/// waits for it instead of working on a history about to change.
+#[derive(Default)]
+pub(crate) struct RewriteLock(Mutex<()>);
+
+/// Run `task` on the blocking thread pool, leaving the invoke thread free
+/// while it goes through the history.
+async fn run_blocking<T: Send + 'static>(
+    task: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    tauri::async_runtime::spawn_blocking(task)
+        .await
+        .map_err(|e| Error::new(ErrorKind::Internal, format!("The operation stopped unexpectedly: {}", e)))?
+}
+
+/// Like [`run_blocking`], for a command moving refs: it runs once the
+/// previous one is done.
+async fn run_exclusive<T: Send + 'static>(
+    app: &AppHandle,
+    task: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    let app = app.clone();
+    run_blocking(move || {
+        let lock = app.state::<RewriteLock>();
+        let _guard = lock.0.lock().unwrap_or_else(|e| e.into_inner());
+        task()
+    }).await
+}
+
+/// Commit hash given by the frontend, which always sends full hashes:
+/// `Oid::from_str` would pad a short one with zeros.
+pub(crate) fn parse_commit_hash(hash: &str) -> Result<Oid, Error> {
+    if hash.len() != 40 {
+        return Err(Error::invalid(format!("Invalid commit hash '{}': expected a full hash", hash)));
+    }
+    Oid::from_str(hash).map_err(|e| Error::invalid(format!("Invalid commit hash: {}", e)))
+}
+
+pub(crate) fn parse_commit_hashes(hashes: &[String]) -> Result<Vec<Oid>, Error> {
+    hashes.iter().map(|hash| parse_commit_hash(hash)).collect()
+}
+
+#[tauri::command]
+pub(crate) async fn get_commits(
+    app: AppHandle,
+    repo_path: String,
+    offset: usize,
+    limit: usize,
+    refs: Option<Vec<RefSelector>>,
+    operation_id: Option<String>,
+) -> Result<CommitPage, Error> {
+    let progress = progress_events(&app, "get_commits", operation_id);
+    run_blocking(move || {
+        let refs = refs.unwrap_or_default();
+        app.state::<RepoCache>().with_repo(&repo_path, progress, |rewriter| rewriter.commits(&refs, offset, limit))
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn search_commits(
+    app: AppHandle,
+    repo_path: String,
+    query: String,
+    offset: usize,
+    limit: usize,
+    refs: Option<Vec<RefSelector>>,
+    operation_id: Option<String>,
+) -> Result<CommitPage, Error> {
+    let progress = progress_events(&app, "search_commits", operation_id);
+    run_blocking(move || {
+        let refs = refs.unwrap_or_default();
+        app.state::<RepoCache>().with_repo(&repo_path, progress, |rewriter| rewriter.search_commits(&refs, &query, offset, limit))
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn edit_commit_message(
+    app: AppHandle,
+    repo_path: String,
+    commit_hash: String,
+    new_message: String,
+    auto_stash: bool,
+    include_untracked: Option<bool>,
+    operation_id: Option<String>,
+) -> Result<RewriteResult, Error> {
+    let progress = progress_events(&app, "edit_commit_message", operation_id);
+    run_exclusive(&app, move || {
+        let commit = parse_commit_hash(&commit_hash)?;
+        let auto_stash = AutoStash::from_flags(auto_stash, include_untracked.unwrap_or(false));
+        Rewriter::open(&repo_path)?.with_progress(progress).edit_message(commit, &new_message, auto_stash)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn preview_edit_commit_message(
+    app: AppHandle,
+    repo_path: String,
+    commit_hash: String,
+    new_message: String,
+    operation_id: Option<String>,
+) -> Result<RewritePreview, Error> {
+    let progress = progress_events(&app, "preview_edit_commit_message", operation_id);
+    run_blocking(move || {
+        let commit = parse_commit_hash(&commit_hash)?;
+        Rewriter::open(&repo_path)?.with_progress(progress).preview_edit_message(commit, &new_message)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn squash_commits(
+    app: AppHandle,
+    repo_path: String,
+    commit_hashes: Vec<String>,
+    new_message: String,
+    auto_stash: bool,
+    include_untracked: Option<bool>,
+    operation_id: Option<String>,
+) -> Result<RewriteResult, Error> {
+    let progress = progress_events(&app, "squash_commits", operation_id);
+    run_exclusive(&app, move || {
+        let commits = parse_commit_hashes(&commit_hashes)?;
+        let auto_stash = AutoStash::from_flags(auto_stash, include_untracked.unwrap_or(false));
+        Rewriter::open(&repo_path)?.with_progress(progress).squash(&commits, &new_message, auto_stash)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn preview_squash_commits(
+    app: AppHandle,
+    repo_path: String,
+    commit_hashes: Vec<String>,
+    new_message: String,
+    operation_id: Option<String>,
+) -> Result<RewritePreview, Error> {
+    let progress = progress_events(&app, "preview_squash_commits", operation_id);
+    run_blocking(move || {
+        let commits = parse_commit_hashes(&commit_hashes)?;
+        Rewriter::open(&repo_path)?.with_progress(progress).preview_squash(&commits, &new_message)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn drop_commits(
+    app: AppHandle,
+    repo_path: String,
+    commit_hashes: Vec<String>,
+    auto_stash: bool,
+    include_untracked: Option<bool>,
+    operation_id: Option<String>,
+) -> Result<RewriteResult, Error> {
+    let progress = progress_events(&app, "drop_commits", operation_id);
+    run_exclusive(&app, move || {
+        let commits = parse_commit_hashes(&commit_hashes)?;
+        let auto_stash = AutoStash::from_flags(auto_stash, include_untracked.unwrap_or(false));
+        Rewriter::open(&repo_path)?.with_progress(progress).drop_commits(&commits, auto_stash)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn split_commit(
+    app: AppHandle,
+    repo_path: String,
+    commit_hash: String,
+    groups: Vec<SplitGroup>,
+    auto_stash: bool,
+    include_untracked: Option<bool>,
+    operation_id: Option<String>,
+) -> Result<RewriteResult, Error> {
+    let progress = progress_events(&app, "split_commit", operation_id);
+    run_exclusive(&app, move || {
+        let commit = parse_commit_hash(&commit_hash)?;
+        let auto_stash = AutoStash::from_flags(auto_stash, include_untracked.unwrap_or(false));
+        Rewriter::open(&repo_path)?.with_progress(progress).split(commit, &groups, auto_stash)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn edit_commit_identity(
+    app: AppHandle,
+    repo_path: String,
+    commit_hashes: Vec<String>,
+    edit: IdentityEdit,
+    auto_stash: bool,
+    include_untracked: Option<bool>,
+    operation_id: Option<String>,
+) -> Result<RewriteResult, Error> {
+    let progress = progress_events(&app, "edit_commit_identity", operation_id);
+    run_exclusive(&app, move || {
+        let commits = parse_commit_hashes(&commit_hashes)?;
+        let auto_stash = AutoStash::from_flags(auto_stash, include_untracked.unwrap_or(false));
+        Rewriter::open(&repo_path)?.with_progress(progress).edit_identity(&commits, &edit, auto_stash)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn apply_mailmap(
+    app: AppHandle,
+    repo_path: String,
+    mailmap: String,
+    all_refs: bool,
+    auto_stash: bool,
+    include_untracked: Option<bool>,
+    operation_id: Option<String>,
+) -> Result<MailmapResult, Error> {
+    let progress = progress_events(&app, "apply_mailmap", operation_id);
+    run_exclusive(&app, move || {
+        let auto_stash = AutoStash::from_flags(auto_stash, include_untracked.unwrap_or(false));
+        Rewriter::open(&repo_path)?.with_progress(progress).apply_mailmap(&mailmap, all_refs, auto_stash)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn execute_rebase_plan(
+    app: AppHandle,
+    repo_path: String,
+    steps: Vec<RebaseStep>,
+    auto_stash: bool,
+    include_untracked: Option<bool>,
+    operation_id: Option<String>,
+) -> Result<RewriteResult, Error> {
+    let progress = progress_events(&app, "execute_rebase_plan", operation_id);
+    run_exclusive(&app, move || {
+        let auto_stash = AutoStash::from_flags(auto_stash, include_untracked.unwrap_or(false));
+        Rewriter::open(&repo_path)?.with_progress(progress).execute_plan(&steps, auto_stash)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn preview_rebase_plan(
+    app: AppHandle,
+    repo_path: String,
+    steps: Vec<RebaseStep>,
+    operation_id: Option<String>,
+) -> Result<RewritePreview, Error> {
+    let progress = progress_events(&app, "preview_rebase_plan", operation_id);
+    run_blocking(move || {
+        Rewriter::open(&repo_path)?.with_progress(progress).preview_plan(&steps)
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn continue_rewrite(
+    app: AppHandle,
+    repo_path: String,
+    operation_id: Option<String>,
+) -> Result<RewriteResult, Error> {
+    let progress = progress_events(&app, "continue_rewrite", operation_id);
+    run_exclusive(&app, move || {
+        Rewriter::open(&repo_path)?.with_progress(progress).continue_rewrite()
+    }).await
+}
+
+#[tauri::command]
+pub(crate) async fn skip_rewrite_step(
+    app: AppHandle,
+    repo_path: String,
+    operation_id: Option<String>,
+) -> Result<RewriteResult, Error> {
+    let progress = progress_events(&app, "skip_rewrite_step", operation_id);
+    run_exclusive(&app, move || {
+        Rewriter::open(&repo_path)?.with_progress(progress).skip_step()
+    }).await
+}
+
+#[tauri::command]
+pub(crate) fn get_rewrite_conflicts(repo_path: String) -> Result<Vec<ConflictEntry>, Error> {
+    Rewriter::open(&repo_path)?.rewrite_conflicts()
+}
+
+/// Returns a warning when auto-stashed changes could not be restored.
+#[tauri::command]
+pub(crate) async fn abort_rewrite(app: AppHandle, repo_path: String) -> Result<Option<String>, Error> {
+    run_exclusive(&app, move || Rewriter::open(&repo_path)?.abort()).await
+}
+
+#[tauri::command]
+pub(crate) fn get_operation_log(repo_path: String) -> Result<Vec<Operation>, Error> {
+    Rewriter::open(&repo_path)?.operations()
+}
+
+#[tauri::command]
+pub(crate) async fn undo_last_operation(app: AppHandle, repo_path: String) -> Result<Operation, Error> {
+    run_exclusive(&app, move || Rewriter::open(&repo_path)?.undo()).await
+}
+
+#[tauri::command]
+pub(crate) async fn redo_operation(app: AppHandle, repo_path: String) -> Result<Operation, Error> {
+    run_exclusive(&app, move || Rewriter::open(&repo_path)?.redo()).await
+}
+
+#[tauri::command]
+pub(crate) async fn list_backups(app: AppHandle, repo_path: String) -> Result<Vec<BackupInfo>, Error> {
+    run_exclusive(&app, move || Rewriter::open(&repo_path)?.backups()).await
+}
+
+#[tauri::command]
+pub(crate) async fn restore_backup(app: AppHandle, repo_path: String, backup_name: String) -> Result<String, Error> {
+    run_exclusive(&app, move || Rewriter::open(&repo_path)?.restore_backup(&backup_name)).await
+}
+
+#[tauri::command]
+pub(crate) async fn prune_backups(app: AppHandle, repo_path: String, max_age_days: u64) -> Result<Vec<String>, Error> {
+    run_exclusive(&app, move || Rewriter::open(&repo_path)?.prune_backups(max_age_days)).await
+}
+
+/// Reflog of `ref_name` (HEAD when omitted), newest first. Entries written by
+/// git-rewrite are flagged, to show how the history was rewritten over time.
+#[tauri::command]
+pub(crate) fn get_reflog(repo_path: String, ref_name: Option<String>) -> Result<Vec<ReflogEntry>, Error> {
+    Rewriter::open(&repo_path)?.reflog(ref_name.as_deref().unwrap_or("HEAD"))
+}
+
+#[tauri::command]
+pub(crate) fn validate_repo(path: String) -> Result<RepoValidation, Error> {
+    Ok(Rewriter::validate(&path))
+}
+
+#[tauri::command]
+pub(crate) fn get_repo_info(repo_path: String) -> Result<RepoInfo, Error> {
+    let branch = Rewriter::open(&repo_path)?.current_branch()?;
+
+    let path = PathBuf::from(&repo_path);
+    let repo_name = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| repo_path.clone());
+
+    Ok(RepoInfo {
+        name: repo_name,
+        path: repo_path,
+        branch,
+    })
+}
+
+#[tauri::command]
+pub(crate) fn get_branches(repo_path: String) -> Result<Vec<BranchInfo>, Error> {
+    Rewriter::open(&repo_path)?.branches()
+}
+
+#[tauri::command]
+pub(crate) async fn get_commit_diff(
+    app: AppHandle,
+    repo_path: String,
+    commit_hash: String,
+    operation_id: Option<String>,
+) -> Result<CommitDiff, Error> {
+    let progress = progress_events(&app, "get_commit_diff", operation_id);
+    run_blocking(move || {
+        let commit = parse_commit_hash(&commit_hash)?;
+        app.state::<RepoCache>().with_repo(&repo_path, progress, |rewriter| rewriter.commit_diff(commit))
+    }).await
+}
+
+/// Returns a warning when auto-stashed changes could not be restored on the new branch.
+#[tauri::command]
+pub(crate) async fn switch_branch(
+    app: AppHandle,
+    repo_path: String,
+    branch_name: String,
+    auto_stash: bool,
+    include_untracked: Option<bool>,
+) -> Result<Option<String>, Error> {
+    run_exclusive(&app, move || {
+        let auto_stash = AutoStash::from_flags(auto_stash, include_untracked.unwrap_or(false));
+        Rewriter::open(&repo_path)?.switch_branch(&branch_name, auto_stash)
+    }).await
+}
@@ -0,0 +1,172 @@
+use crate::error::Error;
+use crate::progress::Progress;
+use crate::split;
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub line_type: String,
+    pub content: String,
+    pub old_line_no: Option<usize>,
+    pub new_line_no: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub status: String,
+    pub old_path: Option<String>,
+    pub insertions: usize,
+    pub deletions: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffStats {
+    pub insertions: usize,
+    pub deletions: usize,
+    pub files_changed: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitDiff {
+    pub hash: String,
+    pub message: String,
+    pub author: String,
+    pub date: i64,
+    pub stats: DiffStats,
+    pub files: Vec<FileDiff>,
+}
+
+/// Changes `oid` made to its first parent, file by file.
+pub(crate) fn commit_diff(repo: &Repository, oid: Oid, progress: &Progress) -> Result<CommitDiff, Error> {
+    let commit = repo.find_commit(oid)
+        .map_err(|e| Error::git("Failed to find commit", e))?;
+
+    let commit_tree = commit.tree()
+        .map_err(|e| Error::git("Failed to get commit tree", e))?;
+
+    // Get parent tree (or empty tree for initial commit)
+    let parent_tree = if commit.parent_count() > 0 {
+        Some(commit.parent(0)
+            .map_err(|e| Error::git("Failed to get parent", e))?
+            .tree()
+            .map_err(|e| Error::git("Failed to get parent tree", e))?)
+    } else {
+        None
+    };
+
+    // Create diff
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit_tree), None)
+        .map_err(|e| Error::git("Failed to create diff", e))?;
+
+    let diff_stats = diff.stats()
+        .map_err(|e| Error::git("Failed to get diff stats", e))?;
+
+    let mut files: Vec<FileDiff> = Vec::new();
+
+    // Iterate through deltas (files)
+    let total_files = diff.deltas().len();
+    for delta_idx in 0..total_files {
+        progress.check_cancelled()?;
+        let delta = diff.get_delta(delta_idx).unwrap();
+
+        let status = match delta.status() {
+            git2::Delta::Added => "Added",
+            git2::Delta::Deleted => "Deleted",
+            git2::Delta::Modified => "Modified",
+            git2::Delta::Renamed => "Renamed",
+            git2::Delta::Copied => "Copied",
+            _ => "Unknown",
+        }.to_string();
+
+        let new_file = delta.new_file();
+        let old_file = delta.old_file();
+
+        let path = new_file.path()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let old_path = if delta.status() == git2::Delta::Renamed {
+            old_file.path().map(|p| p.to_string_lossy().to_string())
+        } else {
+            None
+        };
+
+        let mut lines: Vec<DiffLine> = Vec::new();
+        let mut insertions: usize = 0;
+        let mut deletions: usize = 0;
+
+        // Get patch for this file
+        if let Ok(Some(patch)) = git2::Patch::from_diff(&diff, delta_idx) {
+            for hunk_idx in 0..patch.num_hunks() {
+                if let Ok((hunk, _)) = patch.hunk(hunk_idx) {
+                    // Add hunk header
+                    lines.push(DiffLine {
+                        line_type: "header".to_string(),
+                        content: split::hunk_header(&hunk),
+                        old_line_no: None,
+                        new_line_no: None,
+                    });
+
+                    // Get lines in this hunk
+                    let num_lines = patch.num_lines_in_hunk(hunk_idx).unwrap_or(0);
+                    for line_idx in 0..num_lines {
+                        if let Ok(line) = patch.line_in_hunk(hunk_idx, line_idx) {
+                            let (line_type, old_no, new_no) = match line.origin() {
+                                '+' => {
+                                    insertions += 1;
+                                    ("add".to_string(), None, line.new_lineno().map(|n| n as usize))
+                                }
+                                '-' => {
+                                    deletions += 1;
+                                    ("delete".to_string(), line.old_lineno().map(|n| n as usize), None)
+                                }
+                                ' ' => {
+                                    ("context".to_string(),
+                                     line.old_lineno().map(|n| n as usize),
+                                     line.new_lineno().map(|n| n as usize))
+                                }
+                                _ => continue,
+                            };
+
+                            let content = String::from_utf8_lossy(line.content()).to_string();
+                            lines.push(DiffLine {
+                                line_type,
+                                content,
+                                old_line_no: old_no,
+                                new_line_no: new_no,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        files.push(FileDiff {
+            path,
+            status,
+            old_path,
+            insertions,
+            deletions,
+            lines,
+        });
+        progress.report(delta_idx + 1, total_files);
+    }
+
+    let author = commit.author();
+
+    Ok(CommitDiff {
+        hash: oid.to_string(),
+        message: commit.message().unwrap_or("").to_string(),
+        author: author.name().unwrap_or("Unknown").to_string(),
+        date: commit.time().seconds(),
+        stats: DiffStats {
+            insertions: diff_stats.insertions(),
+            deletions: diff_stats.deletions(),
+            files_changed: diff_stats.files_changed(),
+        },
+        files,
+    })
+}
@@ -0,0 +1,74 @@
+use serde::Serialize;
+use std::fmt;
+
+/// What went wrong, so the frontend can offer a remedy instead of matching
+/// on messages. Serialized as `{ "kind": "...", ...details, "message": "..." }`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Local changes are in the way: stash them (or use auto-stash) and retry
+    DirtyWorktree,
+    /// Some of the selected commits are not in the checked out branch
+    CommitNotInBranch { branch: String },
+    /// The operation cannot go through a merge commit
+    MergeCommitUnsupported { commit: String },
+    /// Changes conflict; `paths` lists the conflicted files when known
+    Conflict { paths: Vec<String> },
+    /// A rewrite is stopped half-way and must be continued, skipped or aborted first
+    RewriteInProgress,
+    /// `path` is not a git repository
+    RepoNotFound { path: String },
+    /// The request does not make sense for this repository (bad hash, empty
+    /// selection, nothing to undo…)
+    InvalidRequest,
+    /// libgit2 failed; `class` and `code` are its error class and code
+    Git { class: String, code: String },
+    /// Reading or writing git-rewrite's files under `.git/` failed
+    Io,
+    /// The command was cancelled with `cancel_operation` before moving any ref
+    Cancelled,
+    /// The command stopped on a bug in git-rewrite
+    Internal,
+}
+
+/// Error returned by every command.
+#[derive(Debug, Serialize)]
+pub struct Error {
+    #[serde(flatten)]
+    pub kind: ErrorKind,
+    /// Human readable description, shown as is
+    pub message: String,
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Error { kind, message: message.into() }
+    }
+
+    pub(crate) fn invalid(message: impl Into<String>) -> Self {
+        Error::new(ErrorKind::InvalidRequest, message)
+    }
+
+    pub(crate) fn io(context: &str, error: impl fmt::Display) -> Self {
+        Error::new(ErrorKind::Io, format!("{}: {}", context, error))
+    }
+
+    /// A libgit2 failure, described as `<context>: <libgit2 message>`.
+    pub(crate) fn git(context: &str, error: git2::Error) -> Self {
+        Error::new(
+            ErrorKind::Git {
+                class: format!("{:?}", error.class()),
+                code: format!("{:?}", error.code()),
+            },
+            format!("{}: {}", context, error),
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
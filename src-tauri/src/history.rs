@@ -0,0 +1,317 @@
+use crate::error::Error;
+use crate::progress::Progress;
+use git2::{BranchType, ErrorCode, Oid, Repository, Revwalk, Sort};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommitInfo {
+    pub hash: String,
+    pub short_hash: String,
+    pub message: String,
+    pub author: String,
+    pub email: String,
+    pub date: i64,
+    pub parent_ids: Vec<String>,
+    /// Refs pointing at this commit, filled in by the commit listing
+    pub decorations: Vec<Decoration>,
+}
+
+/// What a decoration is, in the order `git log --decorate` lists them.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DecorationKind {
+    /// A detached HEAD
+    Head,
+    Branch,
+    RemoteBranch,
+    Tag,
+    Stash,
+}
+
+/// A ref pointing at a commit, like the labels of `git log --decorate`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Decoration {
+    /// Short name: `main`, `origin/main`, `v1.0`, `HEAD` or `stash`
+    pub name: String,
+    pub kind: DecorationKind,
+    /// Whether this is HEAD itself or the branch HEAD is on
+    pub is_head: bool,
+}
+
+/// Decorations of every decorated commit.
+type Decorations = HashMap<Oid, Vec<Decoration>>;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitPage {
+    pub commits: Vec<CommitInfo>,
+    pub has_more: bool,
+    pub total_count: usize,
+}
+
+/// Convert an Oid to CommitInfo, reusing the hash string to avoid redundant allocations
+pub(crate) fn commit_info(repo: &Repository, oid: Oid) -> Option<CommitInfo> {
+    let commit = repo.find_commit(oid).ok()?;
+    let author = commit.author();
+    let hash = oid.to_string();
+    let short_hash = hash[..7].to_string();
+    let parent_ids: Vec<String> = (0..commit.parent_count())
+        .filter_map(|i| commit.parent_id(i).ok())
+        .map(|id| id.to_string())
+        .collect();
+
+    Some(CommitInfo {
+        hash,
+        short_hash,
+        message: commit.message().unwrap_or("").to_string(),
+        author: author.name().unwrap_or("Unknown").to_string(),
+        email: author.email().unwrap_or("").to_string(),
+        date: commit.time().seconds(),
+        parent_ids,
+        decorations: Vec::new(),
+    })
+}
+
+/// Where the history starts from, like the revisions given to `git log`.
+/// Serialized as `{ "kind": "branch", "value": "main" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum RefSelector {
+    /// The checked out commit
+    Head,
+    /// One local branch, by short name
+    Branch(String),
+    /// Every local branch, like `--branches`
+    AllBranches,
+    /// Every remote-tracking branch, like `--remotes`
+    AllRemotes,
+    /// Every tag, like `--tags`
+    Tags,
+    /// Refs matching a glob like `--glob`: `refs/` is implied, and so is a
+    /// trailing `/*` without any `*`, `?` or `[`
+    Glob(String),
+}
+
+impl RefSelector {
+    fn push(&self, repo: &Repository, revwalk: &mut Revwalk) -> Result<(), Error> {
+        match self {
+            RefSelector::Head => revwalk.push_head().map_err(|e| Error::git("Failed to push HEAD", e)),
+            RefSelector::Branch(name) => {
+                let branch = repo.find_branch(name, BranchType::Local).map_err(|e| match e.code() {
+                    ErrorCode::NotFound | ErrorCode::InvalidSpec => Error::invalid(format!("Branch '{}' does not exist", name)),
+                    _ => Error::git(&format!("Failed to find branch '{}'", name), e),
+                })?;
+                let target = branch.get().target().ok_or_else(|| Error::invalid(format!("Branch '{}' has no target", name)))?;
+                revwalk.push(target).map_err(|e| Error::git(&format!("Failed to push branch '{}'", name), e))
+            }
+            RefSelector::AllBranches => revwalk.push_glob("heads").map_err(|e| Error::git("Failed to push branches", e)),
+            RefSelector::AllRemotes => revwalk.push_glob("remotes").map_err(|e| Error::git("Failed to push remote branches", e)),
+            RefSelector::Tags => revwalk.push_glob("tags").map_err(|e| Error::git("Failed to push tags", e)),
+            RefSelector::Glob(pattern) => revwalk.push_glob(pattern)
+                .map_err(|e| Error::git(&format!("Failed to push refs matching '{}'", pattern), e)),
+        }
+    }
+}
+
+/// Commits reachable from `selection`, as of the refs in `refs`.
+struct CommitIndex {
+    selection: Vec<RefSelector>,
+    refs: Vec<(String, Oid)>,
+    /// Children before parents, newest first among unrelated commits
+    commits: Arc<[Oid]>,
+    decorations: Arc<Decorations>,
+}
+
+/// The history of one repository, walked once and kept until a ref moves or
+/// other refs are asked for, so that paging through it does not walk it
+/// again on every call.
+#[derive(Default)]
+pub(crate) struct History {
+    index: Option<CommitIndex>,
+    /// Commits never change, so their details outlive index rebuilds
+    infos: HashMap<Oid, CommitInfo>,
+}
+
+impl History {
+    /// Commits reachable from `selection` (HEAD when empty), walked again
+    /// only when a ref has moved or the selection changed since the last
+    /// call, with the decorations as of the same refs. A cancelled walk
+    /// leaves the index as it was.
+    fn commits(
+        &mut self,
+        repo: &Repository,
+        selection: &[RefSelector],
+        progress: &Progress,
+    ) -> Result<(Arc<[Oid]>, Arc<Decorations>), Error> {
+        let selection = if selection.is_empty() { &[RefSelector::Head][..] } else { selection };
+        let refs = ref_targets(repo)?;
+        if self.index.as_ref().is_none_or(|index| index.refs != refs || index.selection != selection) {
+            let mut revwalk = repo.revwalk().map_err(|e| Error::git("Failed to create revwalk", e))?;
+            revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)
+                .map_err(|e| Error::git("Failed to set sorting", e))?;
+            for selector in selection {
+                selector.push(repo, &mut revwalk)?;
+            }
+            let mut commits = Vec::new();
+            for oid in revwalk {
+                progress.check_cancelled()?;
+                commits.extend(oid.ok());
+            }
+            let decorations = Arc::new(decorations(repo, &refs));
+            self.index = Some(CommitIndex { selection: selection.to_vec(), refs, commits: commits.into(), decorations });
+        }
+        Ok(self.index.as_ref()
+            .map(|index| (index.commits.clone(), index.decorations.clone()))
+            .unwrap_or_default())
+    }
+
+    /// `limit` commits of the history from `selection`, starting at `offset`.
+    pub(crate) fn page(
+        &mut self,
+        repo: &Repository,
+        selection: &[RefSelector],
+        offset: usize,
+        limit: usize,
+        progress: &Progress,
+    ) -> Result<CommitPage, Error> {
+        let (all, decorations) = self.commits(repo, selection, progress)?;
+        let total_count = all.len();
+
+        let mut commits = Vec::with_capacity(limit.min(total_count));
+        for &oid in all.iter().skip(offset).take(limit) {
+            let info = match self.infos.entry(oid) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => match commit_info(repo, oid) {
+                    Some(info) => entry.insert(info),
+                    None => continue,
+                },
+            };
+            commits.push(decorated(info.clone(), oid, &decorations));
+        }
+
+        Ok(CommitPage {
+            has_more: offset + commits.len() < total_count,
+            commits,
+            total_count,
+        })
+    }
+
+    /// Commits of the history from `selection` whose hash starts with
+    /// `query`, or whose message or author contains it, paged like
+    /// [`History::page`].
+    pub(crate) fn search(
+        &mut self,
+        repo: &Repository,
+        selection: &[RefSelector],
+        query: &str,
+        offset: usize,
+        limit: usize,
+        progress: &Progress,
+    ) -> Result<CommitPage, Error> {
+        let query_lower = query.to_lowercase();
+        let (oids, decorations) = self.commits(repo, selection, progress)?;
+
+        // Go through every commit to get an accurate total count; only the
+        // matches are kept in the cache
+        let mut total_count = 0usize;
+        let mut commits = Vec::with_capacity(limit);
+        for (done, &oid) in oids.iter().enumerate() {
+            progress.check_cancelled()?;
+            progress.report(done + 1, oids.len());
+            let info = match self.infos.get(&oid) {
+                Some(info) => info.clone(),
+                None => match commit_info(repo, oid) {
+                    Some(info) => info,
+                    None => continue,
+                },
+            };
+            if !matches(&info, &query_lower) {
+                continue;
+            }
+            total_count += 1;
+            if total_count > offset && commits.len() < limit {
+                self.infos.entry(oid).or_insert_with(|| info.clone());
+                commits.push(decorated(info, oid, &decorations));
+            }
+        }
+
+        Ok(CommitPage {
+            has_more: offset + commits.len() < total_count,
+            commits,
+            total_count,
+        })
+    }
+}
+
+/// Match a commit against a lowercased search query: hash prefix, message or author.
+fn matches(info: &CommitInfo, query_lower: &str) -> bool {
+    info.hash.starts_with(query_lower)
+        || info.message.to_lowercase().contains(query_lower)
+        || info.author.to_lowercase().contains(query_lower)
+        || info.email.to_lowercase().contains(query_lower)
+}
+
+/// HEAD and every ref, with the commit they resolve to. The commit index is
+/// rebuilt when this changes.
+fn ref_targets(repo: &Repository) -> Result<Vec<(String, Oid)>, Error> {
+    let mut refs = Vec::new();
+    if let Ok(head) = repo.head() {
+        refs.push(("HEAD".to_string(), head.target().unwrap_or_else(Oid::zero)));
+    }
+
+    let references = repo.references().map_err(|e| Error::git("Failed to list references", e))?;
+    for reference in references {
+        let reference = reference.map_err(|e| Error::git("Failed to read reference", e))?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            refs.push((name.to_string(), target));
+        }
+    }
+    Ok(refs)
+}
+
+/// Group `refs` (as listed by [`ref_targets`]) by the commit they point at.
+/// Tags are peeled to their commit; refs other than HEAD, branches, remote
+/// branches, tags and the stash are left out.
+fn decorations(repo: &Repository, refs: &[(String, Oid)]) -> Decorations {
+    let head = repo.head().ok().and_then(|head| head.name().map(str::to_string));
+    let mut decorations = Decorations::new();
+    for (name, target) in refs {
+        let (kind, short_name, target) = if name == "HEAD" {
+            // Attached HEAD is shown on its branch instead
+            if head.as_deref() != Some("HEAD") {
+                continue;
+            }
+            (DecorationKind::Head, name.as_str(), *target)
+        } else if let Some(short_name) = name.strip_prefix("refs/heads/") {
+            (DecorationKind::Branch, short_name, *target)
+        } else if let Some(short_name) = name.strip_prefix("refs/remotes/") {
+            (DecorationKind::RemoteBranch, short_name, *target)
+        } else if let Some(short_name) = name.strip_prefix("refs/tags/") {
+            let Ok(commit) = repo.find_object(*target, None).and_then(|object| object.peel_to_commit()) else {
+                continue;
+            };
+            (DecorationKind::Tag, short_name, commit.id())
+        } else if name == "refs/stash" {
+            (DecorationKind::Stash, "stash", *target)
+        } else {
+            continue;
+        };
+        decorations.entry(target).or_default().push(Decoration {
+            name: short_name.to_string(),
+            kind,
+            is_head: head.as_deref() == Some(name.as_str()),
+        });
+    }
+    for list in decorations.values_mut() {
+        list.sort_by(|a, b| (!a.is_head, a.kind, &a.name).cmp(&(!b.is_head, b.kind, &b.name)));
+    }
+    decorations
+}
+
+/// `info` with the decorations of its commit.
+fn decorated(mut info: CommitInfo, oid: Oid, decorations: &Decorations) -> CommitInfo {
+    info.decorations = decorations.get(&oid).cloned().unwrap_or_default();
+    info
+}
@@ -0,0 +1,185 @@
+use crate::error::Error;
+use crate::progress::Progress;
+use crate::reflog;
+use crate::replay::{self, Replay};
+use git2::{Mailmap, Repository, Signature, Sort, Time};
+use serde::{Deserialize, Serialize};
+
+/// New author and committer details for a set of commits. Fields left to
+/// `None` keep the commit's current value; times are seconds since the epoch
+/// and offsets are minutes east of UTC.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IdentityEdit {
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    pub author_time: Option<i64>,
+    pub author_offset: Option<i32>,
+    pub committer_name: Option<String>,
+    pub committer_email: Option<String>,
+    pub committer_time: Option<i64>,
+    pub committer_offset: Option<i32>,
+    /// Replace the committer with the configured identity and the current
+    /// time before applying the `committer_*` fields
+    #[serde(default)]
+    pub reset_committer: bool,
+}
+
+impl IdentityEdit {
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.reset_committer
+            && self.author_name.is_none()
+            && self.author_email.is_none()
+            && self.author_time.is_none()
+            && self.author_offset.is_none()
+            && self.committer_name.is_none()
+            && self.committer_email.is_none()
+            && self.committer_time.is_none()
+            && self.committer_offset.is_none()
+    }
+
+    /// Author and committer of a commit once the edit is applied.
+    pub(crate) fn apply(
+        &self,
+        repo: &Repository,
+        author: &Signature,
+        committer: &Signature,
+    ) -> Result<(Signature<'static>, Signature<'static>), Error> {
+        let new_author = edit_signature(
+            author,
+            self.author_name.as_deref(),
+            self.author_email.as_deref(),
+            self.author_time,
+            self.author_offset,
+        )?;
+
+        let base_committer = if self.reset_committer {
+            repo.signature().map_err(|e| Error::git("Failed to get default signature", e))?
+        } else {
+            committer.to_owned()
+        };
+        let new_committer = edit_signature(
+            &base_committer,
+            self.committer_name.as_deref(),
+            self.committer_email.as_deref(),
+            self.committer_time,
+            self.committer_offset,
+        )?;
+
+        Ok((new_author, new_committer))
+    }
+}
+
+fn edit_signature(
+    signature: &Signature,
+    name: Option<&str>,
+    email: Option<&str>,
+    time: Option<i64>,
+    offset: Option<i32>,
+) -> Result<Signature<'static>, Error> {
+    let when = signature.when();
+    let time = Time::new(
+        time.unwrap_or(when.seconds()),
+        offset.unwrap_or(when.offset_minutes()),
+    );
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => String::from_utf8_lossy(signature.name_bytes()).to_string(),
+    };
+    let email = match email {
+        Some(email) => email.to_string(),
+        None => String::from_utf8_lossy(signature.email_bytes()).to_string(),
+    };
+
+    Signature::new(&name, &email, &time).map_err(|e| Error::invalid(format!("Invalid signature: {}", e)))
+}
+
+/// Outcome of [`rewrite_with_mailmap`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MailmapResult {
+    pub new_hash: String,
+    /// Branches and tags moved to the rewritten history
+    pub updated_refs: Vec<String>,
+    /// Commits whose author or committer was mapped to another identity
+    pub changed_commits: usize,
+    /// Why auto-stashed changes could not be restored; they are left in the stash list
+    pub stash_warning: Option<String>,
+}
+
+/// Rewrite the author and committer of every commit reachable from HEAD, or
+/// from every local branch and tag with `all_refs`, according to `mailmap`
+/// (`.mailmap` format). Commits left unchanged keep their OID, and branches
+/// and tags pointing to rewritten commits are moved onto the new ones.
+pub(crate) fn rewrite_with_mailmap(
+    repo: &Repository,
+    mailmap: &str,
+    all_refs: bool,
+    progress: &Progress,
+) -> Result<MailmapResult, Error> {
+    let mailmap = Mailmap::from_buffer(mailmap).map_err(|e| Error::invalid(format!("Invalid mailmap: {}", e)))?;
+
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+
+    let mut revwalk = repo.revwalk().map_err(|e| Error::git("Failed to create revwalk", e))?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)
+        .map_err(|e| Error::git("Failed to set sorting", e))?;
+    revwalk.push(head_oid).map_err(|e| Error::git("Failed to push HEAD", e))?;
+    if all_refs {
+        for (_, oid) in replay::local_refs(repo)? {
+            revwalk.push(oid).map_err(|e| Error::git("Failed to push reference", e))?;
+        }
+    }
+    let commits = revwalk
+        .map(|oid| oid.map_err(|e| Error::git("Failed to walk", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut replay = Replay::new(repo).with_progress(progress.clone());
+    let mut changed_commits = 0;
+
+    replay.rewrite(&commits, |replay, old_commit| {
+        let author = mailmap.resolve_signature(&old_commit.author())
+            .map_err(|e| Error::git("Failed to resolve author", e))?;
+        let committer = mailmap.resolve_signature(&old_commit.committer())
+            .map_err(|e| Error::git("Failed to resolve committer", e))?;
+
+        let same_identity = |a: &Signature, b: &Signature| a.name_bytes() == b.name_bytes() && a.email_bytes() == b.email_bytes();
+        if same_identity(&author, &old_commit.author()) && same_identity(&committer, &old_commit.committer()) {
+            return Ok(false);
+        }
+
+        let tree = old_commit.tree().map_err(|e| Error::git("Failed to get tree", e))?;
+        let parents = replay.parents(old_commit);
+        replay.commit(
+            old_commit,
+            &author,
+            &committer,
+            old_commit.message().unwrap_or(""),
+            &tree,
+            &parents,
+        )?;
+        changed_commits += 1;
+        Ok(true)
+    })?;
+
+    let log_message = reflog::message(&format!("apply mailmap to {}", reflog::commits(changed_commits)));
+    let mut updated_refs = Vec::new();
+    let new_head_oid = replay.resolve(head_oid).ok_or_else(|| Error::invalid("Rewritten history has no HEAD commit"))?;
+    if new_head_oid != head_oid {
+        replay::move_head(repo, new_head_oid, &log_message)?;
+        if let Some(name) = head.shorthand().filter(|_| head.is_branch()) {
+            updated_refs.push(name.to_string());
+        }
+    }
+
+    // Branches and tags pointing into the rewritten history follow it
+    updated_refs.extend(replay::update_refs(repo, &replay, &log_message, |tagger| {
+        mailmap.resolve_signature(tagger).map_err(|e| Error::git("Failed to resolve tagger", e))
+    })?);
+
+    Ok(MailmapResult {
+        new_hash: new_head_oid.to_string(),
+        updated_refs,
+        changed_commits,
+        stash_warning: None,
+    })
+}
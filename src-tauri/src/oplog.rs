@@ -0,0 +1,336 @@
+use crate::error::Error;
+use crate::backup;
+use crate::reflog;
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Kind of history rewrite recorded in the operation log.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    EditMessage,
+    Squash,
+    Drop,
+    Split,
+    EditIdentity,
+    Mailmap,
+    RebasePlan,
+    RestoreBackup,
+}
+
+impl OperationKind {
+    /// What the operation did, as written in reflog messages.
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            OperationKind::EditMessage => "edit message",
+            OperationKind::Squash => "squash",
+            OperationKind::Drop => "drop",
+            OperationKind::Split => "split",
+            OperationKind::EditIdentity => "edit identity",
+            OperationKind::Mailmap => "mailmap",
+            OperationKind::RebasePlan => "rebase plan",
+            OperationKind::RestoreBackup => "restore backup",
+        }
+    }
+}
+
+/// A ref moved by an operation. `None` means the ref did not exist on that side.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RefUpdate {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// One rewrite, with everything needed to undo and redo it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Operation {
+    pub kind: OperationKind,
+    /// Seconds since the epoch
+    pub timestamp: i64,
+    pub old_head: Option<String>,
+    pub new_head: Option<String>,
+    pub refs: Vec<RefUpdate>,
+    /// Backup refs keeping the previous targets of `refs`
+    #[serde(default)]
+    pub backups: Vec<String>,
+}
+
+/// Targets of the refs a rewrite can move: local branches, tags, and HEAD
+/// when it is detached.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RefSnapshot {
+    refs: BTreeMap<String, String>,
+    /// Commit HEAD points to
+    head: Option<String>,
+    /// Backup ref keeping the target of each ref, taken by [`begin`]
+    #[serde(default)]
+    backups: BTreeMap<String, String>,
+}
+
+/// An operation stopped half-way (rebase plan on `edit` or a conflict),
+/// recorded once it finishes.
+#[derive(Debug, Serialize, Deserialize)]
+struct Pending {
+    kind: OperationKind,
+    before: RefSnapshot,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OperationLog {
+    /// Operations that can be undone, oldest first
+    done: Vec<Operation>,
+    /// Undone operations that can be redone, most recently undone last
+    undone: Vec<Operation>,
+    pending: Option<Pending>,
+}
+
+fn log_path(repo: &Repository) -> PathBuf {
+    repo.path().join("git-rewrite").join("operations.json")
+}
+
+fn load(repo: &Repository) -> Result<OperationLog, Error> {
+    let path = log_path(repo);
+    if !path.exists() {
+        return Ok(OperationLog::default());
+    }
+    let content = fs::read_to_string(path).map_err(|e| Error::io("Failed to read operation log", e))?;
+    serde_json::from_str(&content).map_err(|e| Error::io("Failed to read operation log", e))
+}
+
+fn save(repo: &Repository, log: &OperationLog) -> Result<(), Error> {
+    let path = log_path(repo);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::io("Failed to create state directory", e))?;
+    }
+    let content = serde_json::to_string_pretty(log)
+        .map_err(|e| Error::io("Failed to serialize operation log", e))?;
+    fs::write(path, content).map_err(|e| Error::io("Failed to write operation log", e))
+}
+
+fn head_commit(repo: &Repository) -> Option<String> {
+    repo.head().ok()
+        .and_then(|head| head.target())
+        .map(|oid| oid.to_string())
+}
+
+/// Current time in seconds since the epoch.
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn parse_oid(hash: &str) -> Result<Oid, Error> {
+    Oid::from_str(hash).map_err(|e| Error::invalid(format!("Invalid commit hash: {}", e)))
+}
+
+/// Current targets of the refs a rewrite can move.
+fn snapshot(repo: &Repository) -> Result<RefSnapshot, Error> {
+    let mut refs = BTreeMap::new();
+
+    let references = repo.references().map_err(|e| Error::git("Failed to list references", e))?;
+    for reference in references {
+        let reference = reference.map_err(|e| Error::git("Failed to read reference", e))?;
+        if !(reference.is_branch() || reference.is_tag()) {
+            continue;
+        }
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            refs.insert(name.to_string(), target.to_string());
+        }
+    }
+
+    if repo.head_detached().unwrap_or(false) {
+        if let Some(head) = head_commit(repo) {
+            refs.insert("HEAD".to_string(), head);
+        }
+    }
+
+    Ok(RefSnapshot { refs, head: head_commit(repo), backups: BTreeMap::new() })
+}
+
+/// Snapshot of the refs before an operation, each kept under a backup ref
+/// before anything moves, so that an operation failing half-way or a crash
+/// leaves every old target reachable. The backups of refs the operation does
+/// not move are deleted by [`record`] or [`release`].
+pub(crate) fn begin(repo: &Repository) -> Result<RefSnapshot, Error> {
+    let mut before = snapshot(repo)?;
+    let timestamp = unix_now();
+    for (name, target) in &before.refs {
+        let backup = backup::create(repo, name, parse_oid(target)?, timestamp)?;
+        before.backups.insert(name.clone(), backup);
+    }
+    Ok(before)
+}
+
+/// Delete the backups taken by [`begin`] for refs that did not move, after
+/// an operation that failed. Those of refs it moved before failing are kept.
+pub(crate) fn release(repo: &Repository, before: &RefSnapshot) -> Result<(), Error> {
+    let current = snapshot(repo)?;
+    for (name, backup) in &before.backups {
+        if current.refs.get(name) == before.refs.get(name) {
+            backup::delete(repo, backup)?;
+        }
+    }
+    Ok(())
+}
+
+/// Record an operation that moved refs from `before` to their current targets,
+/// along with the backups keeping their previous targets. Nothing is recorded
+/// when no ref moved. Recording a new operation drops the operations that
+/// could be redone.
+pub(crate) fn record(repo: &Repository, kind: OperationKind, before: &RefSnapshot) -> Result<(), Error> {
+    let mut log = load(repo)?;
+    push(repo, &mut log, kind, before)?;
+    save(repo, &log)
+}
+
+fn push(
+    repo: &Repository,
+    log: &mut OperationLog,
+    kind: OperationKind,
+    before: &RefSnapshot,
+) -> Result<(), Error> {
+    release(repo, before)?;
+    let after = snapshot(repo)?;
+
+    let mut refs: Vec<RefUpdate> = Vec::new();
+    let added = after.refs.keys().filter(|name| !before.refs.contains_key(*name));
+    for name in before.refs.keys().chain(added) {
+        let old = before.refs.get(name).cloned();
+        let new = after.refs.get(name).cloned();
+        if old != new {
+            refs.push(RefUpdate { name: name.clone(), old, new });
+        }
+    }
+    if refs.is_empty() {
+        return Ok(());
+    }
+
+    let timestamp = unix_now();
+    let mut backups = Vec::new();
+    for update in &refs {
+        let Some(old) = &update.old else { continue };
+        // Pending operations saved before backups were taken upfront have none
+        backups.push(match before.backups.get(&update.name) {
+            Some(backup) => backup.clone(),
+            None => backup::create(repo, &update.name, parse_oid(old)?, timestamp)?,
+        });
+    }
+
+    log.done.push(Operation {
+        kind,
+        timestamp,
+        old_head: before.head.clone(),
+        new_head: after.head,
+        refs,
+        backups,
+    });
+    log.undone.clear();
+    Ok(())
+}
+
+/// Remember the refs before an operation that stopped half-way.
+pub(crate) fn begin_pending(repo: &Repository, kind: OperationKind, before: RefSnapshot) -> Result<(), Error> {
+    let mut log = load(repo)?;
+    log.pending = Some(Pending { kind, before });
+    save(repo, &log)
+}
+
+/// Record the stopped operation now that it is done.
+pub(crate) fn finish_pending(repo: &Repository) -> Result<(), Error> {
+    let mut log = load(repo)?;
+    if let Some(pending) = log.pending.take() {
+        push(repo, &mut log, pending.kind, &pending.before)?;
+    }
+    save(repo, &log)
+}
+
+/// Forget the stopped operation, after it was aborted.
+pub(crate) fn discard_pending(repo: &Repository) -> Result<(), Error> {
+    let mut log = load(repo)?;
+    if let Some(pending) = log.pending.take() {
+        release(repo, &pending.before)?;
+        save(repo, &log)?;
+    }
+    Ok(())
+}
+
+/// Recorded operations that can be undone, oldest first.
+pub(crate) fn operations(repo: &Repository) -> Result<Vec<Operation>, Error> {
+    Ok(load(repo)?.done)
+}
+
+/// Put the refs moved by the last operation back where they were.
+pub(crate) fn undo(repo: &Repository) -> Result<Operation, Error> {
+    let mut log = load(repo)?;
+    let operation = log.done.pop().ok_or_else(|| Error::invalid("There is no operation to undo"))?;
+
+    move_refs(repo, &operation, true)?;
+
+    log.undone.push(operation.clone());
+    save(repo, &log)?;
+    Ok(operation)
+}
+
+/// Move the refs again to where the last undone operation put them.
+pub(crate) fn redo(repo: &Repository) -> Result<Operation, Error> {
+    let mut log = load(repo)?;
+    let operation = log.undone.pop().ok_or_else(|| Error::invalid("There is no operation to redo"))?;
+
+    move_refs(repo, &operation, false)?;
+
+    log.done.push(operation.clone());
+    save(repo, &log)?;
+    Ok(operation)
+}
+
+/// Move every ref of `operation` in one transaction, refusing if any of them
+/// moved since.
+fn move_refs(repo: &Repository, operation: &Operation, undo: bool) -> Result<(), Error> {
+    let current = snapshot(repo)?;
+    for update in &operation.refs {
+        let expected = if undo { &update.new } else { &update.old };
+        if current.refs.get(&update.name) != expected.as_ref() {
+            return Err(Error::invalid(format!(
+                "{} has moved since this operation. It cannot be {} safely.",
+                update.name,
+                if undo { "undone" } else { "redone" }
+            )));
+        }
+    }
+
+    let log_message = reflog::message(&format!("{} {}", if undo { "undo" } else { "redo" }, operation.kind.label()));
+    let mut transaction = repo.transaction().map_err(|e| Error::git("Failed to start transaction", e))?;
+    let mut detached_head = None;
+
+    for update in &operation.refs {
+        let target = if undo { &update.old } else { &update.new };
+        if update.name == "HEAD" {
+            // HEAD was detached: moved once the branches are updated
+            detached_head = target.clone();
+            continue;
+        }
+
+        transaction.lock_ref(&update.name)
+            .map_err(|e| Error::git(&format!("Failed to lock {}", update.name), e))?;
+        match target {
+            Some(target) => transaction.set_target(&update.name, parse_oid(target)?, None, &log_message),
+            None => transaction.remove(&update.name),
+        }.map_err(|e| Error::git(&format!("Failed to update {}", update.name), e))?;
+    }
+
+    transaction.commit().map_err(|e| Error::git("Failed to update references", e))?;
+
+    if let Some(head) = detached_head {
+        repo.reference("HEAD", parse_oid(&head)?, true, &log_message)
+            .map_err(|e| Error::git("Failed to move HEAD", e))?;
+    }
+
+    Ok(())
+}
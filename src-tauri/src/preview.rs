@@ -0,0 +1,71 @@
+use crate::error::Error;
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Where a commit ends up in the rewritten history. `new_hash` is `None`
+/// when the commit is removed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitMapping {
+    pub old_hash: String,
+    pub new_hash: Option<String>,
+}
+
+/// What a rewrite would do, computed without moving any ref or touching the
+/// working directory. The new commits exist as unreferenced objects.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RewritePreview {
+    /// Commit HEAD would point to, `None` if the rewrite leaves no commit
+    pub new_head: Option<String>,
+    /// Every walked commit, parents first
+    pub mapping: Vec<CommitMapping>,
+    /// Original hashes of the commits whose hash changes or that are removed
+    pub changed_commits: Vec<String>,
+    /// Branches and tags pointing to a commit whose hash changes
+    pub affected_refs: Vec<String>,
+    /// Original hashes of commits whose changes would not apply; the preview
+    /// leaves them out
+    pub conflicts: Vec<String>,
+}
+
+pub(crate) fn build(
+    repo: &Repository,
+    commits: &[Oid],
+    mapping: &HashMap<Oid, Option<Oid>>,
+    new_head: Option<Oid>,
+    conflicts: &[Oid],
+) -> Result<RewritePreview, Error> {
+    let resolve = |oid: &Oid| mapping.get(oid).copied().unwrap_or(Some(*oid));
+
+    let changed_commits: Vec<Oid> = commits.iter()
+        .filter(|oid| resolve(oid) != Some(**oid))
+        .copied()
+        .collect();
+
+    let mut affected_refs = Vec::new();
+    let references = repo.references().map_err(|e| Error::git("Failed to list references", e))?;
+    for reference in references {
+        let reference = reference.map_err(|e| Error::git("Failed to read reference", e))?;
+        if !(reference.is_branch() || reference.is_tag()) {
+            continue;
+        }
+        if let (Some(name), Ok(commit)) = (reference.shorthand(), reference.peel_to_commit()) {
+            if changed_commits.contains(&commit.id()) {
+                affected_refs.push(name.to_string());
+            }
+        }
+    }
+
+    Ok(RewritePreview {
+        new_head: new_head.map(|oid| oid.to_string()),
+        mapping: commits.iter()
+            .map(|oid| CommitMapping {
+                old_hash: oid.to_string(),
+                new_hash: resolve(oid).map(|new| new.to_string()),
+            })
+            .collect(),
+        changed_commits: changed_commits.iter().map(|oid| oid.to_string()).collect(),
+        affected_refs,
+        conflicts: conflicts.iter().map(|oid| oid.to_string()).collect(),
+    })
+}
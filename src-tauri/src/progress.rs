@@ -0,0 +1,74 @@
+use crate::error::{Error, ErrorKind};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Flag telling the operations holding a clone of it to stop.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Whether an operation still holds a clone of this token.
+    #[cfg(feature = "gui")]
+    pub(crate) fn in_use(&self) -> bool {
+        Arc::strong_count(&self.0) > 1
+    }
+}
+
+/// Where a long operation reports how many commits (or files) it went
+/// through, and learns that it was cancelled. Cheap to clone; the default
+/// one reports nowhere and is never cancelled.
+#[derive(Clone, Default)]
+pub struct Progress {
+    report: Option<Arc<dyn Fn(usize, usize) + Send + Sync>>,
+    cancel: CancelToken,
+}
+
+impl Progress {
+    /// Progress handed to `report` as `(done, total)`.
+    pub fn new(report: impl Fn(usize, usize) + Send + Sync + 'static) -> Self {
+        Progress { report: Some(Arc::new(report)), cancel: CancelToken::default() }
+    }
+
+    /// Stop the operation once `cancel` is cancelled.
+    pub fn with_cancel(self, cancel: CancelToken) -> Self {
+        Progress { cancel, ..self }
+    }
+
+    /// Report that `done` items out of `total` were processed. Only about a
+    /// hundred reports go through for a whole operation, plus the last one.
+    pub(crate) fn report(&self, done: usize, total: usize) {
+        let Some(report) = &self.report else { return };
+        if done == total || done.is_multiple_of((total / 100).max(1)) {
+            report(done, total);
+        }
+    }
+
+    /// Fail with [`ErrorKind::Cancelled`] once the operation was cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<(), Error> {
+        if self.cancel.is_cancelled() {
+            return Err(Error::new(ErrorKind::Cancelled, "The operation was cancelled"));
+        }
+        Ok(())
+    }
+}
+
+/// Progress of a command, as sent to the frontend in the `operation-progress`
+/// event.
+#[derive(Debug, Serialize, Clone)]
+pub struct OperationProgress {
+    /// Name of the command reporting, e.g. `squash_commits`
+    pub operation: String,
+    /// Id the frontend gave the command, if any
+    pub operation_id: Option<String>,
+    pub done: usize,
+    pub total: usize,
+}
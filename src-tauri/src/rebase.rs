@@ -0,0 +1,887 @@
+use crate::error::{Error, ErrorKind};
+use crate::preview::{self, RewritePreview};
+use crate::progress::Progress;
+use crate::reflog;
+use crate::replay::{self, Applied, Replay};
+use crate::rewrite::RewriteResult;
+use git2::{CherrypickOptions, Commit, Index, IndexEntry, Oid, Repository, ResetType, Sort, Tree};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+
+/// What to do with a commit of a rebase plan, mirroring the `git rebase -i` todo actions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RebaseAction {
+    Pick,
+    Reword,
+    Edit,
+    Squash,
+    Fixup,
+    Drop,
+}
+
+/// One line of a rebase plan. `message` is required for `reword` and replaces
+/// the combined message for `squash`; it is ignored by the other actions.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RebaseStep {
+    pub hash: String,
+    pub action: RebaseAction,
+    pub message: Option<String>,
+}
+
+/// How the new parents of a replayed commit are chosen.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum ReplayMode {
+    /// Each commit goes on top of the previous step, so steps can be reordered
+    Linear,
+    /// Each commit keeps its own parents, remapped, so merges are preserved
+    Graph,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum StopReason {
+    Edit,
+    Conflict,
+}
+
+/// The step a rewrite is stopped on.
+#[derive(Debug, Serialize, Deserialize)]
+struct Stop {
+    step: RebaseStep,
+    reason: StopReason,
+    /// Parents the stopped commit gets once its conflicts are resolved
+    parents: Vec<String>,
+}
+
+/// Progress of a rewrite, saved under `.git/` while it is stopped on an
+/// `edit` step or a conflict, or after continuing it was cancelled.
+#[derive(Debug, Serialize, Deserialize)]
+struct RebaseState {
+    mode: ReplayMode,
+    /// Branch to update once the rewrite is done, `None` when HEAD was detached
+    head_name: Option<String>,
+    orig_head: String,
+    /// Last commit created so far, `None` until something is picked onto an empty base
+    tip: Option<String>,
+    /// Original commits folded into `tip`, remapped again by squash/fixup steps
+    tip_sources: Vec<String>,
+    /// `None` once a continuation was cancelled between two steps
+    stopped: Option<Stop>,
+    remaining: VecDeque<RebaseStep>,
+    mapping: HashMap<String, Option<String>>,
+    empty_commits: Vec<String>,
+    /// Stash commit holding the local changes to restore once the rewrite ends
+    auto_stash: Option<String>,
+    /// Reflog message written when the rewrite moves the refs
+    log_message: String,
+}
+
+/// A blob on one side of a conflicted path.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConflictBlob {
+    pub oid: String,
+    pub is_binary: bool,
+    /// Blob content, lossily decoded; empty for binary files
+    pub content: String,
+}
+
+/// A path left conflicted in the index: `base` is the common ancestor,
+/// `ours` the rewritten history and `theirs` the commit being replayed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConflictEntry {
+    pub path: String,
+    pub base: Option<ConflictBlob>,
+    pub ours: Option<ConflictBlob>,
+    pub theirs: Option<ConflictBlob>,
+}
+
+fn state_path(repo: &Repository) -> PathBuf {
+    repo.path().join("git-rewrite").join("rebase-state.json")
+}
+
+fn load_state(repo: &Repository) -> Result<RebaseState, Error> {
+    let content = fs::read_to_string(state_path(repo))
+        .map_err(|_| Error::invalid("No rewrite is in progress."))?;
+    serde_json::from_str(&content).map_err(|e| Error::io("Failed to read rebase state", e))
+}
+
+fn save_state(repo: &Repository, state: &RebaseState) -> Result<(), Error> {
+    let path = state_path(repo);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::io("Failed to create state directory", e))?;
+    }
+    let content = serde_json::to_string_pretty(state)
+        .map_err(|e| Error::io("Failed to serialize rebase state", e))?;
+    fs::write(path, content).map_err(|e| Error::io("Failed to write rebase state", e))
+}
+
+fn clear_state(repo: &Repository) -> Result<(), Error> {
+    let path = state_path(repo);
+    if path.exists() {
+        fs::remove_file(path).map_err(|e| Error::io("Failed to remove rebase state", e))?;
+    }
+    Ok(())
+}
+
+fn parse_oid(hash: &str) -> Result<Oid, Error> {
+    Oid::from_str(hash).map_err(|e| Error::invalid(format!("Invalid commit hash: {}", e)))
+}
+
+/// Refuse to start another rewrite while one is stopped half-way.
+pub(crate) fn ensure_not_in_progress(repo: &Repository) -> Result<(), Error> {
+    if state_path(repo).exists() {
+        return Err(Error::new(
+            ErrorKind::RewriteInProgress,
+            "A rewrite is in progress. Continue, skip or abort it before rewriting history again.",
+        ));
+    }
+    Ok(())
+}
+
+/// Stash of the local changes to restore when the stopped rewrite ends, if any.
+pub(crate) fn auto_stashed(repo: &Repository) -> Result<Option<Oid>, Error> {
+    load_state(repo)?.auto_stash.as_deref().map(parse_oid).transpose()
+}
+
+fn validate_plan(steps: &[RebaseStep]) -> Result<Vec<Oid>, Error> {
+    if steps.is_empty() {
+        return Err(Error::invalid("The rebase plan is empty"));
+    }
+
+    let mut oids = Vec::with_capacity(steps.len());
+    let mut has_tip = false;
+
+    for step in steps {
+        // `Oid::from_str` pads short hashes with zeros instead of looking them up
+        if step.hash.len() != 40 {
+            return Err(Error::invalid(format!("Invalid commit hash '{}': plans take full hashes", step.hash)));
+        }
+        let oid = parse_oid(&step.hash)?;
+        let short = reflog::short(&step.hash);
+        if oids.contains(&oid) {
+            return Err(Error::invalid(format!("Commit {} appears more than once in the plan", short)));
+        }
+        oids.push(oid);
+
+        match step.action {
+            RebaseAction::Reword if step.message.is_none() => {
+                return Err(Error::invalid(format!("Reword of commit {} needs a new message", short)));
+            }
+            RebaseAction::Squash | RebaseAction::Fixup if !has_tip => {
+                return Err(Error::invalid(format!(
+                    "Cannot squash commit {}: there is no previous commit to fold it into",
+                    short
+                )));
+            }
+            RebaseAction::Drop => {}
+            _ => has_tip = true,
+        }
+    }
+
+    Ok(oids)
+}
+
+/// Start executing `steps` (oldest first) from their common base up to HEAD.
+///
+/// The plan must list every commit between the base and HEAD; commits are
+/// removed with the `drop` action. On an `edit` step or a conflict the plan
+/// stops with HEAD detached, see [`resume`], [`skip`] and [`abort`].
+pub(crate) fn start(
+    repo: &Repository,
+    steps: &[RebaseStep],
+    auto_stash: Option<Oid>,
+    progress: &Progress,
+) -> Result<RewriteResult, Error> {
+    let state = linear_state(repo, steps, auto_stash)?;
+    run(repo, state, progress)
+}
+
+/// What executing `steps` would do, without stopping on `edit` steps or
+/// touching HEAD, the working directory or the saved rewrite state.
+/// Commits that would conflict are left out of the preview.
+pub(crate) fn preview(repo: &Repository, steps: &[RebaseStep], progress: &Progress) -> Result<RewritePreview, Error> {
+    let state = linear_state(repo, steps, None)?;
+    let mut execution = Execution::load(repo, &state)?;
+    let mut conflicts = Vec::new();
+
+    for (done, step) in state.remaining.iter().enumerate() {
+        progress.check_cancelled()?;
+        if let Some(stop) = execution.apply(state.mode, step)? {
+            if stop.reason == StopReason::Conflict {
+                let oid = parse_oid(&step.hash)?;
+                conflicts.push(oid);
+                let replacement = match step.action {
+                    RebaseAction::Squash | RebaseAction::Fixup => execution.tip,
+                    _ => stop.parents.first().map(|hash| parse_oid(hash)).transpose()?,
+                };
+                execution.replay.map(oid, replacement);
+            }
+        }
+        progress.report(done + 1, state.remaining.len());
+    }
+
+    let commits = steps.iter()
+        .map(|step| parse_oid(&step.hash))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Dropped and conflicting commits are mapped onto the commit replacing
+    // them so refs follow, but the preview shows them as removed
+    let mut mapping = execution.replay.mapping().clone();
+    for (step, oid) in steps.iter().zip(&commits) {
+        if step.action == RebaseAction::Drop || conflicts.contains(oid) {
+            mapping.insert(*oid, None);
+        }
+    }
+    preview::build(repo, &commits, &mapping, execution.tip, &conflicts)
+}
+
+/// State of a linear rebase plan about to run.
+fn linear_state(repo: &Repository, steps: &[RebaseStep], auto_stash: Option<Oid>) -> Result<RebaseState, Error> {
+    let plan_oids = validate_plan(steps)?;
+
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let current_branch = head.shorthand().unwrap_or("HEAD").to_string();
+    let head_name = if head.is_branch() { head.name().map(|n| n.to_string()) } else { None };
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+
+    // Walk back from HEAD until every commit of the plan has been seen
+    let mut revwalk = repo.revwalk().map_err(|e| Error::git("Failed to create revwalk", e))?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL).map_err(|e| Error::git("Failed to set sorting", e))?;
+    revwalk.push(head_oid).map_err(|e| Error::git("Failed to push HEAD", e))?;
+
+    let mut missing: HashSet<Oid> = plan_oids.iter().copied().collect();
+    let mut range: Vec<Oid> = Vec::new();
+    for oid in revwalk {
+        let oid = oid.map_err(|e| Error::git("Failed to walk", e))?;
+        range.push(oid);
+        missing.remove(&oid);
+        if missing.is_empty() {
+            break;
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(Error::new(
+            ErrorKind::CommitNotInBranch { branch: current_branch.clone() },
+            format!("One or more commits of the plan are not in the current branch '{}'. Ensure all commits belong to this branch.", current_branch),
+        ));
+    }
+
+    let mut base: Option<Oid> = None;
+    for oid in &range {
+        let commit = repo.find_commit(*oid).map_err(|e| Error::git("Failed to find commit", e))?;
+        if commit.parent_count() > 1 {
+            return Err(Error::new(
+                ErrorKind::MergeCommitUnsupported { commit: oid.to_string() },
+                format!(
+                    "Commit {} is a merge commit. Rebase plans only support linear history; use edit message or squash to rewrite across merges.",
+                    reflog::short(&oid.to_string())
+                ),
+            ));
+        }
+        if !plan_oids.contains(oid) {
+            return Err(Error::invalid(format!(
+                "Commit {} is missing from the plan. List every commit up to HEAD and use 'drop' to remove one.",
+                reflog::short(&oid.to_string())
+            )));
+        }
+        base = commit.parent_ids().next();
+    }
+
+    Ok(RebaseState {
+        mode: ReplayMode::Linear,
+        head_name,
+        orig_head: head_oid.to_string(),
+        tip: base.map(|oid| oid.to_string()),
+        tip_sources: Vec::new(),
+        stopped: None,
+        remaining: steps.iter().cloned().collect(),
+        mapping: HashMap::new(),
+        empty_commits: Vec::new(),
+        auto_stash: auto_stash.map(|oid| oid.to_string()),
+        log_message: reflog::message(&format!("rebase plan of {}", reflog::commits(steps.len()))),
+    })
+}
+
+/// Replay `steps`, listed parents first, keeping the shape of the history:
+/// each commit is recreated on top of its own rewritten parents, and a
+/// dropped commit is replaced by its first parent. Only `pick`, `reword`,
+/// `edit` and `drop` make sense here.
+pub(crate) fn start_graph(
+    repo: &Repository,
+    steps: Vec<RebaseStep>,
+    auto_stash: Option<Oid>,
+    progress: &Progress,
+) -> Result<RewriteResult, Error> {
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let head_name = if head.is_branch() { head.name().map(|n| n.to_string()) } else { None };
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+    let dropped = steps.iter().filter(|step| step.action == RebaseAction::Drop).count();
+
+    let state = RebaseState {
+        mode: ReplayMode::Graph,
+        head_name,
+        orig_head: head_oid.to_string(),
+        tip: None,
+        tip_sources: Vec::new(),
+        stopped: None,
+        remaining: steps.into(),
+        mapping: HashMap::new(),
+        empty_commits: Vec::new(),
+        auto_stash: auto_stash.map(|oid| oid.to_string()),
+        log_message: reflog::message(&format!("drop {}", reflog::commits(dropped))),
+    };
+
+    run(repo, state, progress)
+}
+
+/// Continue a stopped rewrite.
+///
+/// On an `edit` stop, changes to tracked files are amended into the stopped
+/// commit. On a conflict, every conflicted path must have been resolved and
+/// staged; the resolved commit is then created and the rewrite goes on. A
+/// rewrite whose continuation was cancelled simply goes on with its next step.
+pub(crate) fn resume(repo: &Repository, progress: &Progress) -> Result<RewriteResult, Error> {
+    let mut state = load_state(repo)?;
+    let Some(stop) = state.stopped.take() else {
+        return run(repo, state, progress);
+    };
+    ensure_head_at_stop(repo, &state, &stop)?;
+
+    let mut index = repo.index().map_err(|e| Error::git("Failed to get index", e))?;
+    // Pick up conflict resolutions staged by other tools since the index was loaded
+    index.read(false).map_err(|e| Error::git("Failed to read index", e))?;
+    if index.has_conflicts() {
+        let paths = conflicted_paths(&index)?;
+        return Err(Error::new(
+            ErrorKind::Conflict { paths: paths.clone() },
+            format!("Resolve the conflicts in {} and stage them before continuing.", paths.join(", ")),
+        ));
+    }
+
+    index.update_all(["*"].iter(), None)
+        .map_err(|e| Error::git("Failed to stage changes", e))?;
+    index.write().map_err(|e| Error::git("Failed to write index", e))?;
+    let tree_oid = index.write_tree().map_err(|e| Error::git("Failed to write tree", e))?;
+    let tree = repo.find_tree(tree_oid).map_err(|e| Error::git("Failed to find tree", e))?;
+
+    match stop.reason {
+        StopReason::Edit => {
+            let head_commit = repo.head()
+                .and_then(|head| head.peel_to_commit())
+                .map_err(|e| Error::git("Failed to get HEAD commit", e))?;
+
+            let tip = if tree_oid != head_commit.tree_id() {
+                head_commit.amend(None, None, None, None, None, Some(&tree))
+                    .map_err(|e| Error::git("Failed to amend commit", e))?
+            } else {
+                head_commit.id()
+            };
+
+            if state.tip.as_deref() == Some(head_commit.id().to_string().as_str()) {
+                // The stopped commit was amended: whatever it replaced now maps to the amended one
+                for source in &state.tip_sources {
+                    state.mapping.insert(source.clone(), Some(tip.to_string()));
+                }
+            } else {
+                // New commits were created on top of the stopped one: later squashes fold into them
+                state.tip_sources.clear();
+            }
+            state.tip = Some(tip.to_string());
+
+            // The working directory already holds the amended tree
+            repo.set_head_detached(tip).map_err(|e| Error::git("Failed to move HEAD", e))?;
+        }
+        StopReason::Conflict => {
+            let commit = repo.find_commit(parse_oid(&stop.step.hash)?)
+                .map_err(|e| Error::git("Failed to find commit", e))?;
+            let parents = stop.parents.iter()
+                .map(|hash| parse_oid(hash))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut execution = Execution::load(repo, &state)?;
+            let created = match stop.step.action {
+                RebaseAction::Squash | RebaseAction::Fixup => {
+                    execution.squash(&stop.step, &commit, &tree)?;
+                    true
+                }
+                _ => execution.pick(&stop.step, &commit, &tree, &parents)?,
+            };
+            execution.store(&mut state);
+
+            repo.cleanup_state().map_err(|e| Error::git("Failed to clean up repository state", e))?;
+
+            // The working directory already holds the resolved tree
+            if let (true, Some(tip)) = (created, &state.tip) {
+                repo.set_head_detached(parse_oid(tip)?).map_err(|e| Error::git("Failed to move HEAD", e))?;
+            }
+
+            if created && stop.step.action == RebaseAction::Edit {
+                return pause(repo, state, Stop { parents: stop.parents, reason: StopReason::Edit, step: stop.step });
+            }
+        }
+    }
+
+    run(repo, state, progress)
+}
+
+/// Leave out the commit a rewrite is stopped on because of a conflict, and go on.
+pub(crate) fn skip(repo: &Repository, progress: &Progress) -> Result<RewriteResult, Error> {
+    let mut state = load_state(repo)?;
+    let stop = state.stopped.take().ok_or_else(|| Error::invalid("The rewrite is not stopped on any commit"))?;
+    if stop.reason != StopReason::Conflict {
+        return Err(Error::invalid("Only a commit stopped on a conflict can be skipped; continue or abort instead."));
+    }
+    ensure_head_at_stop(repo, &state, &stop)?;
+
+    // The skipped commit is replaced by the commit it was being applied onto
+    let onto = repo.head()
+        .and_then(|head| head.peel_to_commit())
+        .map_err(|e| Error::git("Failed to get HEAD commit", e))?;
+    repo.reset(onto.as_object(), ResetType::Hard, None)
+        .map_err(|e| Error::git("Failed to reset HEAD", e))?;
+    repo.cleanup_state().map_err(|e| Error::git("Failed to clean up repository state", e))?;
+
+    let replacement = match stop.step.action {
+        RebaseAction::Squash | RebaseAction::Fixup => state.tip.clone(),
+        _ => stop.parents.first().cloned(),
+    };
+    state.mapping.insert(stop.step.hash, replacement);
+
+    run(repo, state, progress)
+}
+
+/// Commit HEAD was detached on when the rewrite stopped on `stop`: the
+/// stopped commit for `edit`, the commit it is applied onto for a conflict.
+fn stop_point(state: &RebaseState, stop: &Stop) -> Option<String> {
+    match (stop.reason, stop.step.action) {
+        (StopReason::Edit, _) | (StopReason::Conflict, RebaseAction::Squash | RebaseAction::Fixup) => state.tip.clone(),
+        (StopReason::Conflict, _) => stop.parents.first().cloned(),
+    }
+}
+
+/// Refuse to go on when HEAD was moved away from where the rewrite stopped,
+/// since continuing takes the stopped commit from HEAD. Commits added on top
+/// of an `edit` stop are fine.
+fn ensure_head_at_stop(repo: &Repository, state: &RebaseState, stop: &Stop) -> Result<(), Error> {
+    let expected = stop_point(state, stop).as_deref().map(parse_oid).transpose()?;
+    let head = repo.head().ok()
+        .filter(|_| repo.head_detached().unwrap_or(false))
+        .and_then(|head| head.target());
+
+    let in_place = match (head, expected) {
+        (Some(head), Some(expected)) => match stop.reason {
+            StopReason::Edit => replay::in_history_of(repo, head, expected),
+            StopReason::Conflict => head == expected,
+        },
+        _ => false,
+    };
+    if !in_place {
+        return Err(Error::invalid(format!(
+            "HEAD moved since the rewrite stopped on {}. Check out {} again (detached) to continue, or abort the rewrite.",
+            reflog::short(&stop.step.hash),
+            expected.map(|oid| reflog::short(&oid.to_string()).to_string()).unwrap_or_else(|| "the stopped commit".to_string()),
+        )));
+    }
+    Ok(())
+}
+
+/// Abandon a stopped rewrite and put the branch back where it was. Returns
+/// the stash of the local changes auto-stashed when the rewrite started.
+pub(crate) fn abort(repo: &Repository) -> Result<Option<Oid>, Error> {
+    let state = load_state(repo)?;
+    let orig_head = parse_oid(&state.orig_head)?;
+
+    repo.cleanup_state().map_err(|e| Error::git("Failed to clean up repository state", e))?;
+
+    match &state.head_name {
+        Some(name) => repo.set_head(name),
+        None => repo.set_head_detached(orig_head),
+    }.map_err(|e| Error::git("Failed to restore HEAD", e))?;
+
+    let orig_commit = repo.find_commit(orig_head)
+        .map_err(|e| Error::git("Failed to find original HEAD", e))?;
+    repo.reset(orig_commit.as_object(), ResetType::Hard, None)
+        .map_err(|e| Error::git("Failed to reset HEAD", e))?;
+
+    clear_state(repo)?;
+    state.auto_stash.as_deref().map(parse_oid).transpose()
+}
+
+/// Conflicted paths of a rewrite stopped on a conflict, with the blobs of each side.
+pub(crate) fn conflicts(repo: &Repository) -> Result<Vec<ConflictEntry>, Error> {
+    load_state(repo)?;
+
+    let mut index = repo.index().map_err(|e| Error::git("Failed to get index", e))?;
+    index.read(false).map_err(|e| Error::git("Failed to read index", e))?;
+    let conflicts = index.conflicts().map_err(|e| Error::git("Failed to read conflicts", e))?;
+
+    let mut entries = Vec::new();
+    for conflict in conflicts {
+        let conflict = conflict.map_err(|e| Error::git("Failed to read conflict", e))?;
+        let path = [&conflict.our, &conflict.their, &conflict.ancestor]
+            .iter()
+            .find_map(|entry| entry.as_ref())
+            .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+            .unwrap_or_default();
+
+        entries.push(ConflictEntry {
+            path,
+            base: conflict_blob(repo, conflict.ancestor.as_ref())?,
+            ours: conflict_blob(repo, conflict.our.as_ref())?,
+            theirs: conflict_blob(repo, conflict.their.as_ref())?,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn conflict_blob(repo: &Repository, entry: Option<&IndexEntry>) -> Result<Option<ConflictBlob>, Error> {
+    let Some(entry) = entry else {
+        return Ok(None);
+    };
+
+    let blob = repo.find_blob(entry.id).map_err(|e| Error::git("Failed to find blob", e))?;
+    let is_binary = blob.is_binary();
+
+    Ok(Some(ConflictBlob {
+        oid: entry.id.to_string(),
+        is_binary,
+        content: if is_binary { String::new() } else { String::from_utf8_lossy(blob.content()).to_string() },
+    }))
+}
+
+fn conflicted_paths(index: &Index) -> Result<Vec<String>, Error> {
+    let conflicts = index.conflicts().map_err(|e| Error::git("Failed to read conflicts", e))?;
+    let mut paths = Vec::new();
+    for conflict in conflicts {
+        let conflict = conflict.map_err(|e| Error::git("Failed to read conflict", e))?;
+        if let Some(entry) = conflict.our.as_ref().or(conflict.their.as_ref()).or(conflict.ancestor.as_ref()) {
+            paths.push(String::from_utf8_lossy(&entry.path).to_string());
+        }
+    }
+    Ok(paths)
+}
+
+/// In-memory view of the parts of [`RebaseState`] that change while steps are applied.
+struct Execution<'repo> {
+    repo: &'repo Repository,
+    replay: Replay<'repo>,
+    tip: Option<Oid>,
+    tip_sources: Vec<Oid>,
+    empty_commits: Vec<String>,
+}
+
+impl<'repo> Execution<'repo> {
+    fn load(repo: &'repo Repository, state: &RebaseState) -> Result<Self, Error> {
+        let mut replay = Replay::new(repo);
+        for (old, new) in &state.mapping {
+            replay.map(parse_oid(old)?, new.as_deref().map(parse_oid).transpose()?);
+        }
+
+        Ok(Self {
+            repo,
+            replay,
+            tip: state.tip.as_deref().map(parse_oid).transpose()?,
+            tip_sources: state.tip_sources.iter()
+                .map(|hash| parse_oid(hash))
+                .collect::<Result<Vec<_>, _>>()?,
+            empty_commits: state.empty_commits.clone(),
+        })
+    }
+
+    fn store(&self, state: &mut RebaseState) {
+        state.tip = self.tip.map(|oid| oid.to_string());
+        state.tip_sources = self.tip_sources.iter().map(|oid| oid.to_string()).collect();
+        state.mapping = self.replay.mapping().iter()
+            .map(|(old, new)| (old.to_string(), new.map(|oid| oid.to_string())))
+            .collect();
+        state.empty_commits = self.empty_commits.clone();
+    }
+
+    /// Apply one step. Returns the stop to pause on, if any.
+    fn apply(&mut self, mode: ReplayMode, step: &RebaseStep) -> Result<Option<Stop>, Error> {
+        let oid = parse_oid(&step.hash)?;
+        let commit = self.repo.find_commit(oid)
+            .map_err(|e| Error::git("Failed to find commit", e))?;
+
+        let parents: Vec<Oid> = match mode {
+            ReplayMode::Linear => self.tip.into_iter().collect(),
+            ReplayMode::Graph => self.replay.parents(&commit),
+        };
+
+        let (onto, stop_parents) = match step.action {
+            RebaseAction::Drop => {
+                self.replay.map(oid, parents.first().copied());
+                return Ok(None);
+            }
+            RebaseAction::Squash | RebaseAction::Fixup => {
+                let into_oid = self.tip.ok_or_else(|| Error::invalid("There is no previous commit to squash into"))?;
+                let into = self.repo.find_commit(into_oid)
+                    .map_err(|e| Error::git("Failed to find commit", e))?;
+                (vec![into_oid], into.parent_ids().collect::<Vec<_>>())
+            }
+            _ => (parents.clone(), parents.clone()),
+        };
+
+        let tree = match self.replay.apply(&commit, &onto)? {
+            Applied::Clean(tree) => tree,
+            Applied::Conflicted => {
+                return Ok(Some(Stop {
+                    step: step.clone(),
+                    reason: StopReason::Conflict,
+                    parents: stop_parents.iter().map(|oid| oid.to_string()).collect(),
+                }));
+            }
+        };
+
+        match step.action {
+            RebaseAction::Squash | RebaseAction::Fixup => {
+                self.squash(step, &commit, &tree)?;
+            }
+            _ => {
+                let created = self.pick(step, &commit, &tree, &parents)?;
+                if created && step.action == RebaseAction::Edit {
+                    return Ok(Some(Stop {
+                        step: step.clone(),
+                        reason: StopReason::Edit,
+                        parents: Vec::new(),
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Create the commit of a pick/reword/edit step with the given tree.
+    /// Returns `false` when the commit became empty and was left out.
+    fn pick(&mut self, step: &RebaseStep, commit: &Commit<'repo>, tree: &Tree, parents: &[Oid]) -> Result<bool, Error> {
+        let oid = commit.id();
+
+        // Leave out commits whose changes are already there, like `git rebase` does
+        let was_empty = match commit.parent(0) {
+            Ok(parent) => parent.tree_id() == commit.tree_id(),
+            Err(_) => false,
+        };
+        let onto_tree = match parents.first() {
+            Some(parent_oid) => self.repo.find_commit(*parent_oid)
+                .map_err(|e| Error::git("Failed to find parent", e))?
+                .tree_id(),
+            None => self.repo.treebuilder(None)
+                .and_then(|builder| builder.write())
+                .map_err(|e| Error::git("Failed to write empty tree", e))?,
+        };
+        if commit.parent_count() <= 1 && !was_empty && tree.id() == onto_tree {
+            self.empty_commits.push(step.hash.clone());
+            self.replay.map(oid, parents.first().copied());
+            return Ok(false);
+        }
+
+        let message = match step.action {
+            RebaseAction::Reword => step.message.as_deref().unwrap_or(""),
+            _ => commit.message().unwrap_or(""),
+        };
+
+        let new_oid = self.replay.commit(
+            commit,
+            &commit.author(),
+            &commit.committer(),
+            message,
+            tree,
+            parents,
+        )?;
+
+        self.tip = Some(new_oid);
+        self.tip_sources = vec![oid];
+        Ok(true)
+    }
+
+    /// Fold `commit` into the current tip with the given tree.
+    fn squash(&mut self, step: &RebaseStep, commit: &Commit<'repo>, tree: &Tree) -> Result<(), Error> {
+        let into_oid = self.tip.ok_or_else(|| Error::invalid("There is no previous commit to squash into"))?;
+        let into = self.repo.find_commit(into_oid)
+            .map_err(|e| Error::git("Failed to find commit", e))?;
+
+        let into_message = into.message().unwrap_or("");
+        let message = match (step.action, &step.message) {
+            (RebaseAction::Fixup, _) => into_message.to_string(),
+            (_, Some(message)) => message.clone(),
+            (_, None) => format!("{}\n\n{}", into_message.trim_end(), commit.message().unwrap_or("")),
+        };
+        let parents: Vec<Oid> = into.parent_ids().collect();
+
+        let new_oid = self.replay.commit(
+            commit,
+            &into.author(),
+            &into.committer(),
+            &message,
+            tree,
+            &parents,
+        )?;
+
+        self.tip_sources.push(commit.id());
+        for source in &self.tip_sources {
+            self.replay.map(*source, Some(new_oid));
+        }
+        self.tip = Some(new_oid);
+        Ok(())
+    }
+}
+
+/// Save `state` and leave the repository on the stopped step: HEAD detached on
+/// the stopped commit for `edit`, or on the new parent with the conflicting
+/// changes checked out for a conflict.
+fn pause(repo: &Repository, mut state: RebaseState, stop: Stop) -> Result<RewriteResult, Error> {
+    let (stop_at, conflicted_commits) = match stop.reason {
+        StopReason::Edit => (
+            state.tip.as_deref().map(parse_oid).transpose()?.ok_or_else(|| Error::invalid("There is no commit to stop on"))?,
+            Vec::new(),
+        ),
+        StopReason::Conflict => {
+            let onto = stop_point(&state, &stop);
+            let commit = repo.find_commit(parse_oid(&stop.step.hash)?)
+                .map_err(|e| Error::git("Failed to find commit", e))?;
+            let parents = stop.parents.iter().map(|hash| parse_oid(hash)).collect::<Result<Vec<_>, _>>()?;
+            if parents.len() > 2 && replay::side_parents_changed(repo, &commit, &parents) {
+                return Err(Error::new(
+                    ErrorKind::MergeCommitUnsupported { commit: stop.step.hash.clone() },
+                    format!(
+                        "Octopus merge {} cannot be redone on its rewritten parents.",
+                        reflog::short(&stop.step.hash)
+                    ),
+                ));
+            }
+            let onto = match (onto, commit.parent_count()) {
+                (Some(onto), count) if count > 0 => parse_oid(&onto)?,
+                _ => return Err(Error::new(
+                    ErrorKind::Conflict { paths: Vec::new() },
+                    format!(
+                        "Applying commit {} onto an empty history produced conflicts. Reorder or drop commits so that it applies cleanly.",
+                        reflog::short(&stop.step.hash)
+                    ),
+                )),
+            };
+            (onto, vec![stop.step.hash.clone()])
+        }
+    };
+
+    let reason = stop.reason;
+    let hash = stop.step.hash.clone();
+    state.stopped = Some(stop);
+    save_state(repo, &state)?;
+
+    // Detach HEAD so the branch keeps its original position until the end
+    let stop_commit = repo.find_commit(stop_at)
+        .map_err(|e| Error::git("Failed to find commit", e))?;
+    repo.set_head_detached(stop_at)
+        .map_err(|e| Error::git("Failed to detach HEAD", e))?;
+    repo.reset(stop_commit.as_object(), ResetType::Hard, None)
+        .map_err(|e| Error::git("Failed to reset HEAD", e))?;
+
+    if reason == StopReason::Conflict {
+        // Write the conflicting changes to the index and working directory, with markers
+        let commit = repo.find_commit(parse_oid(&hash)?)
+            .map_err(|e| Error::git("Failed to find commit", e))?;
+        let parents = stopped_parents(&state)?;
+        if replay::side_parents_changed(repo, &commit, &parents) {
+            // Redo the merge itself, like `git rebase --rebase-merges`
+            let others = parents.iter().skip(1)
+                .map(|oid| repo.find_annotated_commit(*oid))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| Error::git("Failed to find parent", e))?;
+            let others: Vec<_> = others.iter().collect();
+            repo.merge(&others, None, None)
+                .map_err(|e| Error::git("Failed to merge conflicting commit", e))?;
+        } else {
+            let mut opts = CherrypickOptions::new();
+            if commit.parent_count() > 1 {
+                opts.mainline(1);
+            }
+            repo.cherrypick(&commit, Some(&mut opts))
+                .map_err(|e| Error::git("Failed to apply conflicting commit", e))?;
+        }
+    }
+
+    Ok(RewriteResult {
+        new_hash: stop_at.to_string(),
+        updated_branches: Vec::new(),
+        paused_at: Some(hash),
+        empty_commits: state.empty_commits,
+        conflicted_commits,
+        stash_warning: None,
+    })
+}
+
+/// New parents of the commit `state` is stopped on.
+fn stopped_parents(state: &RebaseState) -> Result<Vec<Oid>, Error> {
+    state.stopped.iter()
+        .flat_map(|stop| &stop.parents)
+        .map(|hash| parse_oid(hash))
+        .collect()
+}
+
+fn run(repo: &Repository, mut state: RebaseState, progress: &Progress) -> Result<RewriteResult, Error> {
+    let mut execution = Execution::load(repo, &state)?;
+
+    let total = state.remaining.len();
+    while let Some(step) = state.remaining.front() {
+        if let Err(e) = progress.check_cancelled() {
+            // Refs have not moved yet; a rewrite that was already stopped
+            // keeps its state so that it can be continued or aborted later
+            if state_path(repo).exists() {
+                execution.store(&mut state);
+                save_state(repo, &state)?;
+            }
+            return Err(e);
+        }
+        let stop = execution.apply(state.mode, step)?;
+        state.remaining.pop_front();
+        if let Some(stop) = stop {
+            execution.store(&mut state);
+            return pause(repo, state, stop);
+        }
+        progress.report(total - state.remaining.len(), total);
+    }
+    execution.store(&mut state);
+
+    let new_head_oid = match state.mode {
+        ReplayMode::Linear => execution.tip,
+        ReplayMode::Graph => execution.replay.resolve(parse_oid(&state.orig_head)?),
+    }.ok_or_else(|| Error::invalid("The rewrite drops every commit, leaving the branch empty"))?;
+
+    let log_message = &state.log_message;
+    replay::move_head(repo, new_head_oid, log_message)?;
+
+    // After a stop HEAD is detached: put it back on the branch, then move the
+    // branch so that both reflogs end with `log_message`
+    if let Some(name) = &state.head_name {
+        if repo.head_detached().unwrap_or(false) {
+            repo.set_head(name).map_err(|e| Error::git("Failed to restore HEAD", e))?;
+            repo.reference(name, new_head_oid, true, log_message)
+                .map_err(|e| Error::git(&format!("Failed to update {}", name), e))?;
+        }
+    }
+
+    clear_state(repo)?;
+
+    let mut updated_branches: Vec<String> = state.head_name
+        .as_deref()
+        .map(|name| name.trim_start_matches("refs/heads/").to_string())
+        .into_iter()
+        .collect();
+    updated_branches.extend(replay::update_refs(repo, &execution.replay, log_message, |tagger| Ok(tagger.to_owned()))?);
+
+    Ok(RewriteResult {
+        new_hash: new_head_oid.to_string(),
+        updated_branches,
+        paused_at: None,
+        empty_commits: state.empty_commits,
+        conflicted_commits: Vec::new(),
+        stash_warning: None,
+    })
+}
@@ -0,0 +1,66 @@
+use crate::error::Error;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the reflog messages written by git-rewrite.
+pub(crate) const PREFIX: &str = "git-rewrite: ";
+
+/// One entry of a ref's reflog.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReflogEntry {
+    /// Target before the update, `None` when the ref was created
+    pub old_hash: Option<String>,
+    pub new_hash: String,
+    pub committer_name: String,
+    pub committer_email: String,
+    /// Seconds since the epoch
+    pub timestamp: i64,
+    pub message: String,
+    /// Whether git-rewrite made this update
+    pub is_rewrite: bool,
+}
+
+/// Reflog message for an operation: `git-rewrite: <description>`.
+pub(crate) fn message(description: &str) -> String {
+    format!("{}{}", PREFIX, description)
+}
+
+/// `count` followed by `commit` or `commits`.
+pub(crate) fn commits(count: usize) -> String {
+    format!("{} commit{}", count, if count == 1 { "" } else { "s" })
+}
+
+/// Abbreviated hash, as shown in reflog messages.
+pub(crate) fn short(hash: &str) -> &str {
+    &hash[..hash.len().min(7)]
+}
+
+/// Reflog of `ref_name` (`HEAD`, `main`, `refs/heads/main`…), newest first.
+pub(crate) fn entries(repo: &Repository, ref_name: &str) -> Result<Vec<ReflogEntry>, Error> {
+    let name = if ref_name == "HEAD" {
+        ref_name.to_string()
+    } else {
+        repo.resolve_reference_from_short_name(ref_name)
+            .map_err(|e| Error::git(&format!("Failed to find reference {}", ref_name), e))?
+            .name()
+            .ok_or_else(|| Error::invalid("Reference name is not valid UTF-8"))?
+            .to_string()
+    };
+
+    let reflog = repo.reflog(&name).map_err(|e| Error::git(&format!("Failed to read reflog of {}", name), e))?;
+    Ok(reflog.iter()
+        .map(|entry| {
+            let committer = entry.committer();
+            let message = entry.message().unwrap_or("").to_string();
+            ReflogEntry {
+                old_hash: Some(entry.id_old()).filter(|oid| !oid.is_zero()).map(|oid| oid.to_string()),
+                new_hash: entry.id_new().to_string(),
+                committer_name: String::from_utf8_lossy(committer.name_bytes()).to_string(),
+                committer_email: String::from_utf8_lossy(committer.email_bytes()).to_string(),
+                timestamp: committer.when().seconds(),
+                is_rewrite: message.starts_with(PREFIX),
+                message,
+            }
+        })
+        .collect())
+}
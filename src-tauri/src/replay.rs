@@ -0,0 +1,481 @@
+use crate::error::{Error, ErrorKind};
+use crate::progress::Progress;
+use git2::build::CheckoutBuilder;
+use git2::{Commit, ErrorCode, ObjectType, Oid, Repository, Signature, Sort, Tree};
+use std::collections::{HashMap, HashSet};
+
+/// Collect every commit reachable from `head` that may need to be recreated
+/// when `roots` are rewritten, parents first.
+///
+/// The walk stops at the parents of `roots`, so side branches that were
+/// forked before the rewritten range and merged back afterwards are listed
+/// too: they simply keep their OID when none of their parents changed.
+/// Local branches and tags containing one of `roots` are walked as well, so
+/// that they can be moved onto the rewritten history with [`update_refs`].
+pub(crate) fn collect_commits_to_replay(
+    repo: &Repository,
+    head: Oid,
+    roots: &[Oid],
+) -> Result<Vec<Oid>, Error> {
+    let mut revwalk = repo.revwalk().map_err(|e| Error::git("Failed to create revwalk", e))?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)
+        .map_err(|e| Error::git("Failed to set sorting", e))?;
+    revwalk.push(head).map_err(|e| Error::git("Failed to push HEAD", e))?;
+
+    for (_, tip) in local_refs(repo)? {
+        let contains_root = roots.iter().any(|root| {
+            tip == *root || repo.graph_descendant_of(tip, *root).unwrap_or(false)
+        });
+        if contains_root {
+            revwalk.push(tip).map_err(|e| Error::git("Failed to push reference", e))?;
+        }
+    }
+
+    for root in roots {
+        let commit = repo.find_commit(*root)
+            .map_err(|e| Error::git("Failed to find commit", e))?;
+        for parent_id in commit.parent_ids() {
+            revwalk.hide(parent_id).map_err(|e| Error::git("Failed to hide parent", e))?;
+        }
+    }
+
+    revwalk
+        .map(|oid| oid.map_err(|e| Error::git("Failed to walk", e)))
+        .collect()
+}
+
+/// Point the checked out branch (or detached HEAD) at `new_head`.
+///
+/// The index and working directory are only updated when the tree of HEAD
+/// changes, through a safe checkout that fails rather than overwrite local
+/// changes; nothing is moved in that case.
+pub(crate) fn move_head(repo: &Repository, new_head: Oid, log_message: &str) -> Result<(), Error> {
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let old_tree = head.peel_to_tree().map_err(|e| Error::git("Failed to get HEAD tree", e))?;
+    let new_commit = repo.find_commit(new_head)
+        .map_err(|e| Error::git("Failed to find new HEAD", e))?;
+
+    if new_commit.tree_id() != old_tree.id() {
+        let mut checkout = CheckoutBuilder::new();
+        checkout.safe();
+        repo.checkout_tree(new_commit.as_object(), Some(&mut checkout))
+            .map_err(|e| match e.code() {
+                // Local changes to files the rewrite modifies
+                ErrorCode::Conflict => Error::new(
+                    ErrorKind::DirtyWorktree,
+                    format!("Failed to check out the rewritten history, commit or stash your local changes first: {}", e),
+                ),
+                _ => Error::git("Failed to check out the rewritten history", e),
+            })?;
+    }
+
+    match head.name().filter(|_| head.is_branch()) {
+        Some(name) => repo.reference(name, new_head, true, log_message).map(|_| ()),
+        // Written as a direct ref so the HEAD reflog gets `log_message`
+        None => repo.reference("HEAD", new_head, true, log_message).map(|_| ()),
+    }.map_err(|e| Error::git("Failed to move HEAD", e))
+}
+
+/// Whether `commit` is a merge whose parents other than the first no longer
+/// have the same content in `parents`, its new parents. Its changes relative
+/// to the first parent would then bring back what those side branches had
+/// before the rewrite, so the merge has to be redone instead.
+pub(crate) fn side_parents_changed(repo: &Repository, commit: &Commit, parents: &[Oid]) -> bool {
+    if commit.parent_count() < 2 {
+        return false;
+    }
+    let tree_of = |oid: &Oid| repo.find_commit(*oid).map(|commit| commit.tree_id()).ok();
+    let old: Vec<_> = commit.parent_ids().skip(1).map(|oid| tree_of(&oid)).collect();
+    let new: Vec<_> = parents.iter().skip(1).map(tree_of).collect();
+    old != new
+}
+
+/// Whether `oid` is HEAD or one of its ancestors.
+pub(crate) fn in_history_of(repo: &Repository, head: Oid, oid: Oid) -> bool {
+    oid == head || repo.graph_descendant_of(head, oid).unwrap_or(false)
+}
+
+/// Local branches and tags, except the checked out branch, with the commit
+/// they point to.
+pub(crate) fn local_refs(repo: &Repository) -> Result<Vec<(String, Oid)>, Error> {
+    let head_name = repo.head().ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.name().map(|name| name.to_string()));
+
+    let mut refs = Vec::new();
+    let references = repo.references().map_err(|e| Error::git("Failed to list references", e))?;
+    for reference in references {
+        let reference = reference.map_err(|e| Error::git("Failed to read reference", e))?;
+        if !(reference.is_branch() || reference.is_tag()) {
+            continue;
+        }
+        let Some(name) = reference.name() else { continue };
+        if head_name.as_deref() == Some(name) {
+            continue;
+        }
+        if let Ok(commit) = reference.peel_to_commit() {
+            refs.push((name.to_string(), commit.id()));
+        }
+    }
+    Ok(refs)
+}
+
+/// Move every local branch and tag, except the checked out branch, that
+/// points to a rewritten commit onto its new version, like
+/// `git rebase --update-refs`. Annotated tags are recreated on the new commit
+/// with their tagger passed through `map_tagger`. Refs to removed commits are
+/// left alone. Moved branches get `log_message` in their reflog. Returns the
+/// short names of the moved refs.
+pub(crate) fn update_refs<F>(
+    repo: &Repository,
+    replay: &Replay,
+    log_message: &str,
+    map_tagger: F,
+) -> Result<Vec<String>, Error>
+where
+    F: Fn(&Signature) -> Result<Signature<'static>, Error>,
+{
+    let mut updated = Vec::new();
+    for (name, old_oid) in local_refs(repo)? {
+        let new_oid = match replay.resolve(old_oid) {
+            Some(new_oid) if new_oid != old_oid => new_oid,
+            _ => continue,
+        };
+
+        let reference = repo.find_reference(&name).map_err(|e| Error::git("Failed to find reference", e))?;
+        let tag = reference.peel(ObjectType::Tag).ok().and_then(|object| object.into_tag().ok());
+
+        match tag {
+            Some(tag) => {
+                let target = repo.find_object(new_oid, None)
+                    .map_err(|e| Error::git("Failed to find commit", e))?;
+                let tagger = match tag.tagger() {
+                    Some(tagger) => map_tagger(&tagger)?,
+                    None => repo.signature().map_err(|e| Error::git("Failed to get default signature", e))?,
+                };
+                repo.tag(
+                    tag.name().unwrap_or(name.trim_start_matches("refs/tags/")),
+                    &target,
+                    &tagger,
+                    tag.message().unwrap_or(""),
+                    true,
+                ).map_err(|e| Error::git("Failed to recreate tag", e))?;
+            }
+            None => {
+                repo.reference(&name, new_oid, true, log_message)
+                    .map_err(|e| Error::git(&format!("Failed to update {}", name), e))?;
+            }
+        }
+
+        updated.push(name.trim_start_matches("refs/heads/").trim_start_matches("refs/tags/").to_string());
+    }
+    Ok(updated)
+}
+
+/// Commits of `oids` that have no ancestor in `oids`: the roots to pass to
+/// [`collect_commits_to_replay`] for a selection of commits.
+pub(crate) fn oldest_commits(repo: &Repository, oids: &HashSet<Oid>) -> Vec<Oid> {
+    oids.iter()
+        .filter(|oid| {
+            !oids.iter().any(|other| {
+                other != *oid && repo.graph_descendant_of(**oid, *other).unwrap_or(false)
+            })
+        })
+        .copied()
+        .collect()
+}
+
+/// Outcome of applying a commit's changes onto a new parent.
+pub(crate) enum Applied<'repo> {
+    Clean(Tree<'repo>),
+    /// The changes do not apply cleanly onto the new parent
+    Conflicted,
+}
+
+/// Recreates commits on top of rewritten parents while keeping track of the
+/// old → new OID mapping, so merge commits get all of their parents remapped.
+pub(crate) struct Replay<'repo> {
+    repo: &'repo Repository,
+    mapping: HashMap<Oid, Option<Oid>>,
+    /// Leave out commits that do not apply instead of failing, see [`Replay::preview`]
+    skip_conflicts: bool,
+    conflicts: Vec<Oid>,
+    progress: Progress,
+}
+
+impl<'repo> Replay<'repo> {
+    pub(crate) fn new(repo: &'repo Repository) -> Self {
+        Self {
+            repo,
+            mapping: HashMap::new(),
+            skip_conflicts: false,
+            conflicts: Vec::new(),
+            progress: Progress::default(),
+        }
+    }
+
+    /// Report each commit gone through by [`Replay::rewrite`] to `progress`,
+    /// and stop it when `progress` is cancelled.
+    pub(crate) fn with_progress(self, progress: Progress) -> Self {
+        Self { progress, ..self }
+    }
+
+    /// A replay that keeps going when a commit does not apply: the commit is
+    /// listed in [`Replay::conflicts`] and replaced by its first parent, so
+    /// the rest of the history can still be previewed.
+    pub(crate) fn preview(repo: &'repo Repository) -> Self {
+        Self {
+            skip_conflicts: true,
+            ..Self::new(repo)
+        }
+    }
+
+    /// Commits left out because they did not apply, in a preview.
+    pub(crate) fn conflicts(&self) -> &[Oid] {
+        &self.conflicts
+    }
+
+    /// New OID of `oid`, or `oid` itself when it was left untouched.
+    /// `None` means the commit was removed and nothing replaces it, which
+    /// happens when a root commit is folded into a later one.
+    pub(crate) fn resolve(&self, oid: Oid) -> Option<Oid> {
+        self.mapping.get(&oid).copied().unwrap_or(Some(oid))
+    }
+
+    /// Old → new OIDs of every commit rewritten so far.
+    pub(crate) fn mapping(&self) -> &HashMap<Oid, Option<Oid>> {
+        &self.mapping
+    }
+
+    /// Record that `old` is replaced by `new` in the rewritten history.
+    pub(crate) fn map(&mut self, old: Oid, new: Option<Oid>) {
+        self.mapping.insert(old, new);
+    }
+
+    /// Parents of `commit` remapped onto the rewritten history, without
+    /// duplicates. Empty for a root commit, or one whose parents were removed.
+    pub(crate) fn parents(&self, commit: &Commit) -> Vec<Oid> {
+        let mut parents: Vec<Oid> = Vec::with_capacity(commit.parent_count());
+        for parent_id in commit.parent_ids().filter_map(|oid| self.resolve(oid)) {
+            if !parents.contains(&parent_id) {
+                parents.push(parent_id);
+            }
+        }
+        parents
+    }
+
+    /// Recreate `old` with the given metadata, tree and parents and map it to
+    /// the result. A commit identical to `old` keeps its original OID.
+    pub(crate) fn commit(
+        &mut self,
+        old: &Commit,
+        author: &Signature,
+        committer: &Signature,
+        message: &str,
+        tree: &Tree,
+        parents: &[Oid],
+    ) -> Result<Oid, Error> {
+        let unchanged = old.parent_ids().eq(parents.iter().copied())
+            && old.tree_id() == tree.id()
+            && old.message() == Some(message)
+            && same_signature(&old.author(), author)
+            && same_signature(&old.committer(), committer);
+
+        let new_oid = if unchanged {
+            old.id()
+        } else {
+            let parent_commits = parents
+                .iter()
+                .map(|oid| self.repo.find_commit(*oid))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| Error::git("Failed to find parent", e))?;
+            let parent_refs: Vec<&Commit> = parent_commits.iter().collect();
+
+            self.repo.commit(
+                None,
+                author,
+                committer,
+                message,
+                tree,
+                &parent_refs,
+            ).map_err(|e| Error::git("Failed to create commit", e))?
+        };
+
+        self.map(old.id(), Some(new_oid));
+        Ok(new_oid)
+    }
+
+    /// Tree obtained by applying the changes `commit` introduced on top of
+    /// its new `parents` (none for an empty history), like `git cherry-pick`
+    /// does.
+    ///
+    /// When the first new parent has the same tree as the original one, the
+    /// commit's tree is reused untouched. Otherwise the change is replayed as
+    /// a three-way merge; merge commits are applied relative to their first
+    /// parent (`cherry-pick -m 1`), unless their other parents changed too,
+    /// in which case the merge is redone (see [`side_parents_changed`]).
+    pub(crate) fn apply(&self, commit: &Commit<'repo>, parents: &[Oid]) -> Result<Applied<'repo>, Error> {
+        if side_parents_changed(self.repo, commit, parents) {
+            return self.remerge(commit, parents);
+        }
+
+        let onto = parents.first().copied();
+        let tree = commit.tree().map_err(|e| Error::git("Failed to get tree", e))?;
+        let base_tree = match commit.parent_ids().next() {
+            Some(parent_id) => Some(self.tree_of(parent_id)?),
+            None => None,
+        };
+        let onto_commit = match onto {
+            Some(onto) => Some(self.repo.find_commit(onto).map_err(|e| Error::git("Failed to find commit", e))?),
+            None => None,
+        };
+
+        if base_tree.as_ref().map(|t| t.id()) == onto_commit.as_ref().map(|c| c.tree_id()) {
+            return Ok(Applied::Clean(tree));
+        }
+
+        let mut index = match (&base_tree, &onto_commit) {
+            (Some(_), Some(onto_commit)) => {
+                let mainline = if commit.parent_count() > 1 { 1 } else { 0 };
+                self.repo.cherrypick_commit(commit, onto_commit, mainline, None)
+            }
+            // A root commit, or a commit moved onto an empty history: diff against the empty tree
+            _ => {
+                let empty_tree = self.empty_tree()?;
+                let onto_tree = match &onto_commit {
+                    Some(onto_commit) => onto_commit.tree().map_err(|e| Error::git("Failed to get tree", e))?,
+                    None => empty_tree.clone(),
+                };
+                self.repo.merge_trees(base_tree.as_ref().unwrap_or(&empty_tree), &onto_tree, &tree, None)
+            }
+        }.map_err(|e| Error::git("Failed to apply commit", e))?;
+
+        if index.has_conflicts() {
+            return Ok(Applied::Conflicted);
+        }
+
+        let tree_oid = index.write_tree_to(self.repo)
+            .map_err(|e| Error::git("Failed to write tree", e))?;
+        let tree = self.repo.find_tree(tree_oid).map_err(|e| Error::git("Failed to find tree", e))?;
+        Ok(Applied::Clean(tree))
+    }
+
+    /// Merge `commit` again on its new `parents`: the parents are merged, and
+    /// whatever the original merge changed on top of merging its own parents
+    /// (conflict resolutions, amended content) is applied to the result.
+    fn remerge(&self, commit: &Commit<'repo>, parents: &[Oid]) -> Result<Applied<'repo>, Error> {
+        let old_parents: Vec<Oid> = commit.parent_ids().collect();
+        let (Some(old_merge), Some(new_merge)) = (self.merged_tree(&old_parents)?, self.merged_tree(parents)?) else {
+            return Ok(Applied::Conflicted);
+        };
+        let tree = commit.tree().map_err(|e| Error::git("Failed to get tree", e))?;
+
+        let mut index = self.repo.merge_trees(&old_merge, &new_merge, &tree, None)
+            .map_err(|e| Error::git("Failed to merge commit", e))?;
+        if index.has_conflicts() {
+            return Ok(Applied::Conflicted);
+        }
+
+        let tree_oid = index.write_tree_to(self.repo)
+            .map_err(|e| Error::git("Failed to write tree", e))?;
+        let tree = self.repo.find_tree(tree_oid).map_err(|e| Error::git("Failed to find tree", e))?;
+        Ok(Applied::Clean(tree))
+    }
+
+    /// Tree of `parents` merged together, `None` when they conflict. Octopus
+    /// merges are not redone and give `None` as well.
+    fn merged_tree(&self, parents: &[Oid]) -> Result<Option<Tree<'repo>>, Error> {
+        let [ours, theirs] = match parents {
+            [only] => return self.tree_of(*only).map(Some),
+            [ours, theirs] => [ours, theirs].map(|oid| self.repo.find_commit(*oid)),
+            _ => return Ok(None),
+        };
+        let (ours, theirs) = (
+            ours.map_err(|e| Error::git("Failed to find parent", e))?,
+            theirs.map_err(|e| Error::git("Failed to find parent", e))?,
+        );
+
+        let mut index = self.repo.merge_commits(&ours, &theirs, None)
+            .map_err(|e| Error::git("Failed to merge parents", e))?;
+        if index.has_conflicts() {
+            return Ok(None);
+        }
+        let tree_oid = index.write_tree_to(self.repo)
+            .map_err(|e| Error::git("Failed to write tree", e))?;
+        self.repo.find_tree(tree_oid).map(Some).map_err(|e| Error::git("Failed to find tree", e))
+    }
+
+    /// Like [`Replay::apply`], failing when the changes do not apply cleanly.
+    pub(crate) fn apply_changes(&self, commit: &Commit<'repo>, parents: &[Oid]) -> Result<Tree<'repo>, Error> {
+        match self.apply(commit, parents)? {
+            Applied::Clean(tree) => Ok(tree),
+            Applied::Conflicted => Err(Error::new(
+                ErrorKind::Conflict { paths: Vec::new() },
+                format!(
+                    "Applying commit {} produced conflicts. Reorder or drop commits so that it applies cleanly.",
+                    &commit.id().to_string()[..7]
+                ),
+            )),
+        }
+    }
+
+    fn tree_of(&self, commit_oid: Oid) -> Result<Tree<'repo>, Error> {
+        self.repo.find_commit(commit_oid)
+            .and_then(|commit| commit.tree())
+            .map_err(|e| Error::git("Failed to get tree", e))
+    }
+
+    fn empty_tree(&self) -> Result<Tree<'repo>, Error> {
+        let oid = self.repo.treebuilder(None)
+            .and_then(|builder| builder.write())
+            .map_err(|e| Error::git("Failed to write empty tree", e))?;
+        self.repo.find_tree(oid).map_err(|e| Error::git("Failed to find tree", e))
+    }
+
+    /// Replay `commits`, parents first. `rewrite` is given each commit and
+    /// returns `false` for those it leaves alone, which are then [picked](Replay::pick).
+    pub(crate) fn rewrite<F>(&mut self, commits: &[Oid], mut rewrite: F) -> Result<(), Error>
+    where
+        F: FnMut(&mut Self, &Commit<'repo>) -> Result<bool, Error>,
+    {
+        for (done, oid) in commits.iter().enumerate() {
+            self.progress.check_cancelled()?;
+            let old_commit = self.repo.find_commit(*oid)
+                .map_err(|e| Error::git("Failed to find commit", e))?;
+
+            if !rewrite(self, &old_commit)? {
+                self.pick(&old_commit)?;
+            }
+            self.progress.report(done + 1, commits.len());
+        }
+        Ok(())
+    }
+
+    /// Replay `old` on top of its remapped parents, re-applying its changes
+    /// when the first parent's content changed.
+    pub(crate) fn pick(&mut self, old: &Commit<'repo>) -> Result<(), Error> {
+        let parents = self.parents(old);
+        if self.skip_conflicts {
+            if let Applied::Conflicted = self.apply(old, &parents)? {
+                self.conflicts.push(old.id());
+                self.map(old.id(), parents.first().copied());
+                return Ok(());
+            }
+        }
+        let tree = self.apply_changes(old, &parents)?;
+
+        self.commit(
+            old,
+            &old.author(),
+            &old.committer(),
+            old.message().unwrap_or(""),
+            &tree,
+            &parents,
+        )?;
+        Ok(())
+    }
+}
+
+fn same_signature(a: &Signature, b: &Signature) -> bool {
+    a.name_bytes() == b.name_bytes() && a.email_bytes() == b.email_bytes() && a.when() == b.when()
+}
@@ -0,0 +1,174 @@
+use crate::error::{Error, ErrorKind};
+use git2::{BranchType, ErrorCode, Oid, Repository, StatusOptions};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepoValidation {
+    pub valid: bool,
+    pub has_commits: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepoInfo {
+    pub name: String,
+    pub path: String,
+    pub branch: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BranchInfo {
+    pub name: String,
+    pub is_current: bool,
+}
+
+/// Commit named on the command line: a full or abbreviated hash, or any
+/// revision `git rev-parse` understands (`HEAD~2`, a branch…).
+pub(crate) fn resolve_commit(repo: &Repository, rev: &str) -> Result<Oid, Error> {
+    repo.revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map(|commit| commit.id())
+        .map_err(|e| Error::invalid(format!("'{}' does not name a commit: {}", rev, e.message())))
+}
+
+pub(crate) fn open(path: &Path) -> Result<Repository, Error> {
+    Repository::open(path).map_err(|e| match e.code() {
+        ErrorCode::NotFound => Error::new(
+            ErrorKind::RepoNotFound { path: path.display().to_string() },
+            format!("{} is not a git repository", path.display()),
+        ),
+        _ => Error::git("Failed to open repo", e),
+    })
+}
+
+/// Whether `path` is a repository the app can work on, with the reason when
+/// it is not.
+pub(crate) fn validate(path: &Path) -> RepoValidation {
+    // Try to open as a git repository
+    let repo = match Repository::open(path) {
+        Ok(r) => r,
+        Err(e) => {
+            return RepoValidation {
+                valid: false,
+                has_commits: false,
+                error: Some(format!("Not a valid Git repository: {}", e)),
+            };
+        }
+    };
+
+    // Check if HEAD exists and is valid
+    let head = match repo.head() {
+        Ok(h) => h,
+        Err(e) => {
+            // Repository exists but HEAD is invalid (could be empty repo)
+            if e.code() == ErrorCode::UnbornBranch {
+                return RepoValidation {
+                    valid: true,
+                    has_commits: false,
+                    error: Some("Repository has no commits yet".to_string()),
+                };
+            }
+            return RepoValidation {
+                valid: false,
+                has_commits: false,
+                error: Some(format!("Invalid HEAD reference: {}", e)),
+            };
+        }
+    };
+
+    // Check if HEAD points to a valid commit
+    let result = match head.peel_to_commit() {
+        Ok(_) => RepoValidation {
+            valid: true,
+            has_commits: true,
+            error: None,
+        },
+        Err(e) => RepoValidation {
+            valid: true,
+            has_commits: false,
+            error: Some(format!("HEAD does not point to a valid commit: {}", e)),
+        },
+    };
+
+    result
+}
+
+pub(crate) fn check_working_directory_clean(repo: &Repository) -> Result<(), Error> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(false);
+
+    let statuses = repo.statuses(Some(&mut opts))
+        .map_err(|e| Error::git("Failed to get status", e))?;
+
+    if !statuses.is_empty() {
+        return Err(Error::new(
+            ErrorKind::DirtyWorktree,
+            "Working directory has uncommitted changes. Please commit or stash them first.",
+        ));
+    }
+    Ok(())
+}
+
+/// Bring the index and working directory in line with HEAD after refs were
+/// moved, without adding a reflog entry.
+pub(crate) fn checkout_head(repo: &Repository) -> Result<(), Error> {
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))
+        .map_err(|e| Error::git("Failed to checkout", e))
+}
+
+/// Short name of the checked out branch, `HEAD` when detached.
+pub(crate) fn current_branch(repo: &Repository) -> Result<String, Error> {
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    Ok(head.shorthand().unwrap_or("HEAD").to_string())
+}
+
+/// Local branches, the current one first, then by name.
+pub(crate) fn branches(repo: &Repository) -> Result<Vec<BranchInfo>, Error> {
+    let head = repo.head().ok();
+    let current_branch = head.as_ref()
+        .and_then(|h| h.shorthand())
+        .unwrap_or("");
+
+    let branches = repo.branches(Some(BranchType::Local))
+        .map_err(|e| Error::git("Failed to list branches", e))?;
+
+    let mut result: Vec<BranchInfo> = branches
+        .filter_map(|b| {
+            let (branch, _) = b.ok()?;
+            let name = branch.name().ok()??.to_string();
+            Some(BranchInfo {
+                is_current: name == current_branch,
+                name,
+            })
+        })
+        .collect();
+
+    // Sort: current branch first, then alphabetically
+    result.sort_by(|a, b| {
+        if a.is_current && !b.is_current {
+            std::cmp::Ordering::Less
+        } else if !a.is_current && b.is_current {
+            std::cmp::Ordering::Greater
+        } else {
+            a.name.cmp(&b.name)
+        }
+    });
+
+    Ok(result)
+}
+
+/// Check out the local branch `branch_name`, overwriting the working directory.
+pub(crate) fn switch_branch(repo: &Repository, branch_name: &str) -> Result<(), Error> {
+    // Verify the branch exists
+    {
+        let _branch = repo.find_branch(branch_name, BranchType::Local)
+            .map_err(|e| Error::git(&format!("Failed to find branch '{}'", branch_name), e))?;
+    }
+
+    // Checkout the branch
+    repo.set_head(&format!("refs/heads/{}", branch_name))
+        .map_err(|e| Error::git("Failed to set HEAD", e))?;
+
+    checkout_head(repo)
+}
@@ -0,0 +1,423 @@
+use crate::error::{Error, ErrorKind};
+use crate::identity::IdentityEdit;
+use crate::preview::{self, RewritePreview};
+use crate::progress::Progress;
+use crate::rebase::{self, RebaseAction, RebaseStep};
+use crate::reflog;
+use crate::replay::{self, Replay};
+use crate::split::{self, SplitGroup};
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RewriteResult {
+    pub new_hash: String,
+    pub updated_branches: Vec<String>,
+    /// Original hash of the commit a rewrite stopped on, `None` once done
+    pub paused_at: Option<String>,
+    /// Original hashes of commits removed because their changes were already applied
+    pub empty_commits: Vec<String>,
+    /// Original hash of the commit whose changes did not apply; the rewrite
+    /// is paused on it until the conflicts are resolved, skipped or aborted
+    pub conflicted_commits: Vec<String>,
+    /// Why auto-stashed changes could not be restored; they are left in the stash list
+    pub stash_warning: Option<String>,
+}
+
+/// Preview of a replay that did not move any ref yet.
+fn preview_replay(repo: &Repository, commits: &[Oid], replay: &Replay) -> Result<RewritePreview, Error> {
+    let head_oid = repo.head()
+        .map_err(|e| Error::git("Failed to get HEAD", e))?
+        .target()
+        .ok_or_else(|| Error::invalid("HEAD has no target"))?;
+    preview::build(repo, commits, replay.mapping(), replay.resolve(head_oid), replay.conflicts())
+}
+
+/// Move the current branch (or detached HEAD) to the rewritten HEAD commit,
+/// along with every other branch and tag pointing to a rewritten commit.
+fn finish_rewrite(repo: &Repository, replay: &Replay, log_message: &str) -> Result<RewriteResult, Error> {
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let current_branch = head.shorthand().unwrap_or("HEAD").to_string();
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+
+    let new_head_oid = replay.resolve(head_oid).ok_or_else(|| Error::invalid("Rewritten history has no HEAD commit"))?;
+
+    // Update HEAD first: it is the only step that can fail on local changes
+    replay::move_head(repo, new_head_oid, log_message)?;
+
+    let mut updated_branches = vec![current_branch];
+    updated_branches.extend(replay::update_refs(repo, replay, log_message, |tagger| Ok(tagger.to_owned()))?);
+
+    Ok(RewriteResult {
+        new_hash: new_head_oid.to_string(),
+        updated_branches,
+        paused_at: None,
+        empty_commits: Vec::new(),
+        conflicted_commits: Vec::new(),
+        stash_warning: None,
+    })
+}
+
+pub(crate) fn rewrite_commit_message(
+    repo: &Repository,
+    commit: Oid,
+    new_message: &str,
+    progress: &Progress,
+) -> Result<RewriteResult, Error> {
+    let mut replay = Replay::new(repo).with_progress(progress.clone());
+    replay_commit_message(repo, &mut replay, commit, new_message)?;
+    finish_rewrite(repo, &replay, &reflog::message(&format!("reword {}", reflog::short(&commit.to_string()))))
+}
+
+pub(crate) fn preview_commit_message(
+    repo: &Repository,
+    commit: Oid,
+    new_message: &str,
+    progress: &Progress,
+) -> Result<RewritePreview, Error> {
+    let mut replay = Replay::preview(repo).with_progress(progress.clone());
+    let commits = replay_commit_message(repo, &mut replay, commit, new_message)?;
+    preview_replay(repo, &commits, &replay)
+}
+
+/// Recreate the history from the target commit to HEAD with the new message,
+/// without moving any ref. Returns the walked commits, parents first.
+fn replay_commit_message<'repo>(
+    repo: &'repo Repository,
+    replay: &mut Replay<'repo>,
+    target_oid: Oid,
+    new_message: &str,
+) -> Result<Vec<Oid>, Error> {
+    repo.find_commit(target_oid)
+        .map_err(|e| Error::git("Failed to find target commit", e))?;
+
+    // Get current branch name
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let current_branch = head.shorthand().unwrap_or("HEAD").to_string();
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+
+    // Verify commit is in current branch
+    if !repo.graph_descendant_of(head_oid, target_oid).unwrap_or(false) && head_oid != target_oid {
+        return Err(Error::new(
+            ErrorKind::CommitNotInBranch { branch: current_branch.clone() },
+            format!(
+                "Commit {} is not in the current branch '{}'. Switch to a branch that contains this commit, or verify you selected the correct commit.",
+                reflog::short(&target_oid.to_string()), current_branch
+            ),
+        ));
+    }
+
+    // Collect commits between the target and HEAD, parents first (merges included)
+    let commits_to_rewrite = replay::collect_commits_to_replay(repo, head_oid, &[target_oid])?;
+
+    // Build new commit graph
+    replay.rewrite(&commits_to_rewrite, |replay, old_commit| {
+        if old_commit.id() != target_oid {
+            return Ok(false);
+        }
+
+        let tree = old_commit.tree()
+            .map_err(|e| Error::git("Failed to get tree", e))?;
+        let parents = replay.parents(old_commit);
+
+        replay.commit(
+            old_commit,
+            &old_commit.author(),
+            &old_commit.committer(),
+            new_message,
+            &tree,
+            &parents,
+        )?;
+        Ok(true)
+    })?;
+
+    Ok(commits_to_rewrite)
+}
+
+pub(crate) fn squash_commits_impl(
+    repo: &Repository,
+    commits: &[Oid],
+    new_message: &str,
+    progress: &Progress,
+) -> Result<RewriteResult, Error> {
+    let mut replay = Replay::new(repo).with_progress(progress.clone());
+    replay_squash(repo, &mut replay, commits, new_message)?;
+    finish_rewrite(repo, &replay, &reflog::message(&format!("squash {}", reflog::commits(commits.len()))))
+}
+
+pub(crate) fn preview_squash(
+    repo: &Repository,
+    commits: &[Oid],
+    new_message: &str,
+    progress: &Progress,
+) -> Result<RewritePreview, Error> {
+    let mut replay = Replay::preview(repo).with_progress(progress.clone());
+    let commits = replay_squash(repo, &mut replay, commits, new_message)?;
+    preview_replay(repo, &commits, &replay)
+}
+
+/// Recreate the history with the selected commits squashed, without moving
+/// any ref. Returns the walked commits, parents first.
+fn replay_squash<'repo>(
+    repo: &'repo Repository,
+    replay: &mut Replay<'repo>,
+    commits: &[Oid],
+    new_message: &str,
+) -> Result<Vec<Oid>, Error> {
+    let squash_oids: HashSet<Oid> = commits
+        .iter()
+        .copied()
+        .filter(|oid| repo.find_commit(*oid).is_ok())
+        .collect();
+
+    if squash_oids.len() < 2 {
+        return Err(Error::invalid("Need at least 2 valid commits to squash"));
+    }
+
+    // The oldest commit is the only selected one without a selected ancestor
+    let oldest_oid = match replay::oldest_commits(repo, &squash_oids)[..] {
+        [oid] => oid,
+        _ => return Err(Error::invalid("Selected commits must be on a single line of history to be squashed. Select commits that follow each other and try again.")),
+    };
+
+    // Get the parent of the oldest commit to squash
+    let oldest_commit = repo.find_commit(oldest_oid)
+        .map_err(|e| Error::git("Failed to find oldest commit", e))?;
+
+    // None when squashing into the initial commit: the result becomes the new root
+    let base_parent_oid = oldest_commit.parent_ids().next();
+
+    // Get current branch info
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let current_branch = head.shorthand().unwrap_or("HEAD").to_string();
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+
+    // Walk from HEAD to find all commits we need to replay, parents first
+    let all_commits = replay::collect_commits_to_replay(repo, head_oid, &[oldest_oid])?;
+
+    if squash_oids.iter().any(|oid| !replay::in_history_of(repo, head_oid, *oid)) {
+        return Err(Error::new(
+            ErrorKind::CommitNotInBranch { branch: current_branch.clone() },
+            format!("One or more selected commits are not in the current branch '{}'. Ensure all commits belong to this branch.", current_branch),
+        ));
+    }
+
+    // The newest commit comes last in topological order and must contain all the others
+    let newest_oid = *all_commits.iter().rev().find(|oid| squash_oids.contains(oid)).unwrap();
+    if squash_oids.iter().any(|oid| {
+        *oid != newest_oid && !repo.graph_descendant_of(newest_oid, *oid).unwrap_or(false)
+    }) {
+        return Err(Error::invalid("Selected commits must be on a single line of history to be squashed. Select commits that follow each other and try again."));
+    }
+
+    // A branch forked from a folded commit has no commit to go onto: the base
+    // lacks the folded changes and the squashed commit has later ones
+    let forked = all_commits.iter().any(|oid| {
+        !squash_oids.contains(oid)
+            && !repo.graph_descendant_of(*oid, newest_oid).unwrap_or(false)
+            && squash_oids.iter().any(|folded| repo.graph_descendant_of(*oid, *folded).unwrap_or(false))
+    });
+    if forked {
+        return Err(Error::invalid("A branch was forked from one of the selected commits. Squash only the commits after the fork, or rebase the branch first."));
+    }
+
+    // Rewrite commits
+    // Keep the author/committer from the first (oldest) commit in squash
+    let squash_author = oldest_commit.author().to_owned();
+    let squash_committer = oldest_commit.committer().to_owned();
+
+    // Get the tree from the newest squash commit upfront (this is the final state we want)
+    let newest_commit = repo.find_commit(newest_oid)
+        .map_err(|e| Error::git("Failed to find newest commit", e))?;
+    let squash_tree = newest_commit.tree()
+        .map_err(|e| Error::git("Failed to get tree", e))?;
+
+    replay.rewrite(&all_commits, |replay, old_commit| {
+        let oid = old_commit.id();
+        if oid == newest_oid {
+            // Create the squashed commit using the newest commit's tree
+            let parents = replay.parents(old_commit);
+
+            replay.commit(
+                old_commit,
+                &squash_author,
+                &squash_committer,
+                new_message,
+                &squash_tree,
+                &parents,
+            )?;
+        } else if squash_oids.contains(&oid) {
+            // Folded into the squashed commit: descendants are replayed onto the base
+            // (or become roots when the initial commit is squashed)
+            replay.map(oid, base_parent_oid);
+        } else {
+            // Regular commit - just replay it
+            return Ok(false);
+        }
+        Ok(true)
+    })?;
+
+    // Refs to a folded commit follow its changes into the squashed commit
+    let squashed = replay.resolve(newest_oid);
+    for oid in squash_oids.iter().filter(|oid| **oid != newest_oid) {
+        replay.map(*oid, squashed);
+    }
+
+    Ok(all_commits)
+}
+
+pub(crate) fn drop_commits_impl(
+    repo: &Repository,
+    commits: &[Oid],
+    auto_stash: Option<Oid>,
+    progress: &Progress,
+) -> Result<RewriteResult, Error> {
+    let drop_oids: HashSet<Oid> = commits.iter().copied().collect();
+
+    // Get current branch info
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let current_branch = head.shorthand().unwrap_or("HEAD").to_string();
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+
+    // Walk from HEAD down to the oldest dropped commits, parents first
+    let roots = replay::oldest_commits(repo, &drop_oids);
+    let all_commits = replay::collect_commits_to_replay(repo, head_oid, &roots)?;
+
+    if drop_oids.iter().any(|oid| !replay::in_history_of(repo, head_oid, *oid)) {
+        return Err(Error::new(
+            ErrorKind::CommitNotInBranch { branch: current_branch.clone() },
+            format!("One or more selected commits are not in the current branch '{}'. Ensure all commits belong to this branch.", current_branch),
+        ));
+    }
+
+    // Every other commit is picked back on top of its rewritten parents
+    let steps = all_commits
+        .iter()
+        .map(|oid| RebaseStep {
+            hash: oid.to_string(),
+            action: if drop_oids.contains(oid) { RebaseAction::Drop } else { RebaseAction::Pick },
+            message: None,
+        })
+        .collect();
+
+    rebase::start_graph(repo, steps, auto_stash, progress)
+}
+
+pub(crate) fn split_commit_impl(
+    repo: &Repository,
+    target_oid: Oid,
+    groups: &[SplitGroup],
+    progress: &Progress,
+) -> Result<RewriteResult, Error> {
+    if groups.len() < 2 {
+        return Err(Error::invalid("A commit must be split into at least two groups"));
+    }
+
+    let target = repo.find_commit(target_oid)
+        .map_err(|e| Error::git("Failed to find target commit", e))?;
+
+    if target.parent_count() > 1 {
+        return Err(Error::new(
+            ErrorKind::MergeCommitUnsupported { commit: target_oid.to_string() },
+            "Merge commits cannot be split",
+        ));
+    }
+
+    // Get current branch name
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let current_branch = head.shorthand().unwrap_or("HEAD").to_string();
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+
+    // Verify commit is in current branch
+    if !repo.graph_descendant_of(head_oid, target_oid).unwrap_or(false) && head_oid != target_oid {
+        return Err(Error::new(
+            ErrorKind::CommitNotInBranch { branch: current_branch.clone() },
+            format!(
+                "Commit {} is not in the current branch '{}'. Switch to a branch that contains this commit, or verify you selected the correct commit.",
+                reflog::short(&target_oid.to_string()), current_branch
+            ),
+        ));
+    }
+
+    let trees = split::piece_trees(repo, &target, groups)?;
+    let commits_to_rewrite = replay::collect_commits_to_replay(repo, head_oid, &[target_oid])?;
+
+    let mut replay = Replay::new(repo).with_progress(progress.clone());
+
+    replay.rewrite(&commits_to_rewrite, |replay, old_commit| {
+        if old_commit.id() != target_oid {
+            return Ok(false);
+        }
+
+        // Chain the pieces; the target ends up mapped to the last one
+        let mut parents = replay.parents(old_commit);
+        for (group, tree_oid) in groups.iter().zip(&trees) {
+            let tree = repo.find_tree(*tree_oid)
+                .map_err(|e| Error::git("Failed to find tree", e))?;
+            let piece = replay.commit(
+                old_commit,
+                &old_commit.author(),
+                &old_commit.committer(),
+                &group.message,
+                &tree,
+                &parents,
+            )?;
+            parents = vec![piece];
+        }
+        Ok(true)
+    })?;
+
+    let description = format!("split {} into {}", reflog::short(&target_oid.to_string()), reflog::commits(groups.len()));
+    finish_rewrite(repo, &replay, &reflog::message(&description))
+}
+
+pub(crate) fn rewrite_commit_identity(
+    repo: &Repository,
+    commits: &[Oid],
+    edit: &IdentityEdit,
+    progress: &Progress,
+) -> Result<RewriteResult, Error> {
+    let target_oids: HashSet<Oid> = commits.iter().copied().collect();
+
+    // Get current branch info
+    let head = repo.head().map_err(|e| Error::git("Failed to get HEAD", e))?;
+    let current_branch = head.shorthand().unwrap_or("HEAD").to_string();
+    let head_oid = head.target().ok_or_else(|| Error::invalid("HEAD has no target"))?;
+
+    // Walk from HEAD down to the oldest selected commits, parents first
+    let roots = replay::oldest_commits(repo, &target_oids);
+    let commits_to_rewrite = replay::collect_commits_to_replay(repo, head_oid, &roots)?;
+
+    if target_oids.iter().any(|oid| !replay::in_history_of(repo, head_oid, *oid)) {
+        return Err(Error::new(
+            ErrorKind::CommitNotInBranch { branch: current_branch.clone() },
+            format!("One or more selected commits are not in the current branch '{}'. Ensure all commits belong to this branch.", current_branch),
+        ));
+    }
+
+    let mut replay = Replay::new(repo).with_progress(progress.clone());
+
+    replay.rewrite(&commits_to_rewrite, |replay, old_commit| {
+        if !target_oids.contains(&old_commit.id()) {
+            return Ok(false);
+        }
+
+        let tree = old_commit.tree()
+            .map_err(|e| Error::git("Failed to get tree", e))?;
+        let parents = replay.parents(old_commit);
+        let (author, committer) = edit.apply(repo, &old_commit.author(), &old_commit.committer())?;
+
+        replay.commit(
+            old_commit,
+            &author,
+            &committer,
+            old_commit.message().unwrap_or(""),
+            &tree,
+            &parents,
+        )?;
+        Ok(true)
+    })?;
+
+    finish_rewrite(repo, &replay, &reflog::message(&format!("edit identity of {}", reflog::commits(target_oids.len()))))
+}
@@ -0,0 +1,382 @@
+use crate::backup::{self, BackupInfo};
+use crate::diff::{self, CommitDiff};
+use crate::error::Error;
+use crate::history::{CommitPage, History, RefSelector};
+use crate::identity::{self, IdentityEdit, MailmapResult};
+use crate::oplog::{self, Operation, OperationKind};
+use crate::preview::RewritePreview;
+use crate::progress::Progress;
+use crate::rebase::{self, ConflictEntry, RebaseStep};
+use crate::reflog::{self, ReflogEntry};
+use crate::repo::{self, BranchInfo};
+use crate::rewrite::{self, RewriteResult};
+use crate::split::SplitGroup;
+use crate::stash;
+use git2::{Oid, Repository};
+use std::path::Path;
+
+/// What to do with local changes before a rewrite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutoStash {
+    /// Leave them be; rewrites that check out other commits refuse to run
+    #[default]
+    Off,
+    /// Stash changes to tracked files and restore them afterwards
+    Tracked,
+    /// Stash untracked files as well
+    IncludeUntracked,
+}
+
+impl AutoStash {
+    /// From the `auto_stash` and `include_untracked` flags of the commands.
+    pub fn from_flags(auto_stash: bool, include_untracked: bool) -> Self {
+        match (auto_stash, include_untracked) {
+            (false, _) => AutoStash::Off,
+            (true, false) => AutoStash::Tracked,
+            (true, true) => AutoStash::IncludeUntracked,
+        }
+    }
+
+    fn save(self, repo: &mut Repository) -> Result<Option<Oid>, Error> {
+        match self {
+            AutoStash::Off => Ok(None),
+            AutoStash::Tracked => stash::save(repo, false),
+            AutoStash::IncludeUntracked => stash::save(repo, true),
+        }
+    }
+}
+
+/// Everything git-rewrite does to one repository, without the app: reading
+/// the history, previewing and rewriting it, and going back.
+///
+/// Rewrites move refs like the app does, with the same checks, reflog
+/// messages and operation log entries, so the app can undo them.
+pub struct Rewriter {
+    repo: Repository,
+    progress: Progress,
+    history: History,
+}
+
+impl Rewriter {
+    /// Open the repository at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        repo::open(path.as_ref()).map(Rewriter::new)
+    }
+
+    pub fn new(repo: Repository) -> Self {
+        Rewriter { repo, progress: Progress::default(), history: History::default() }
+    }
+
+    /// Report the progress of the following operations to `progress`, and
+    /// stop them once it is cancelled.
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.set_progress(progress);
+        self
+    }
+
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    pub fn repository(&self) -> &Repository {
+        &self.repo
+    }
+
+    /// Whether `path` is a repository git-rewrite can work on.
+    pub fn validate(path: impl AsRef<Path>) -> repo::RepoValidation {
+        repo::validate(path.as_ref())
+    }
+
+    // Reading
+
+    /// `limit` commits of the history from `refs` (HEAD when empty),
+    /// starting at `offset`. The history is walked again only when a ref
+    /// moved or other refs are asked for since the last call.
+    pub fn commits(&mut self, refs: &[RefSelector], offset: usize, limit: usize) -> Result<CommitPage, Error> {
+        self.history.page(&self.repo, refs, offset, limit, &self.progress)
+    }
+
+    /// Commits of the history from `refs` (HEAD when empty) whose hash
+    /// starts with `query`, or whose message or author contains it.
+    pub fn search_commits(&mut self, refs: &[RefSelector], query: &str, offset: usize, limit: usize) -> Result<CommitPage, Error> {
+        self.history.search(&self.repo, refs, query, offset, limit, &self.progress)
+    }
+
+    pub fn commit_diff(&self, commit: Oid) -> Result<CommitDiff, Error> {
+        diff::commit_diff(&self.repo, commit, &self.progress)
+    }
+
+    /// Short name of the checked out branch, `HEAD` when detached.
+    pub fn current_branch(&self) -> Result<String, Error> {
+        repo::current_branch(&self.repo)
+    }
+
+    pub fn branches(&self) -> Result<Vec<BranchInfo>, Error> {
+        repo::branches(&self.repo)
+    }
+
+    /// Reflog of `ref_name`, newest first.
+    pub fn reflog(&self, ref_name: &str) -> Result<Vec<ReflogEntry>, Error> {
+        reflog::entries(&self.repo, ref_name)
+    }
+
+    /// Operation log, newest first.
+    pub fn operations(&self) -> Result<Vec<Operation>, Error> {
+        oplog::operations(&self.repo)
+    }
+
+    pub fn backups(&self) -> Result<Vec<BackupInfo>, Error> {
+        backup::list(&self.repo)
+    }
+
+    /// Conflicted files of the rewrite stopped on a conflict.
+    pub fn rewrite_conflicts(&self) -> Result<Vec<ConflictEntry>, Error> {
+        rebase::conflicts(&self.repo)
+    }
+
+    // Previews, moving no ref
+
+    pub fn preview_edit_message(&self, commit: Oid, new_message: &str) -> Result<RewritePreview, Error> {
+        rewrite::preview_commit_message(&self.repo, commit, new_message, &self.progress)
+    }
+
+    pub fn preview_squash(&self, commits: &[Oid], new_message: &str) -> Result<RewritePreview, Error> {
+        if commits.len() < 2 {
+            return Err(Error::invalid("Need at least 2 commits to squash"));
+        }
+        rewrite::preview_squash(&self.repo, commits, new_message, &self.progress)
+    }
+
+    pub fn preview_plan(&self, steps: &[RebaseStep]) -> Result<RewritePreview, Error> {
+        rebase::preview(&self.repo, steps, &self.progress)
+    }
+
+    // Rewrites
+
+    /// Give `commit` a new message, replaying its descendants.
+    pub fn edit_message(&mut self, commit: Oid, new_message: &str, auto_stash: AutoStash) -> Result<RewriteResult, Error> {
+        let (result, stash_warning) = self.rewrite(OperationKind::EditMessage, auto_stash, |repo, progress| {
+            rewrite::rewrite_commit_message(repo, commit, new_message, progress)
+        })?;
+        Ok(RewriteResult { stash_warning, ..result })
+    }
+
+    /// Fold `commits`, a line of history, into one commit with `new_message`.
+    pub fn squash(&mut self, commits: &[Oid], new_message: &str, auto_stash: AutoStash) -> Result<RewriteResult, Error> {
+        if commits.is_empty() {
+            return Err(Error::invalid("No commits selected"));
+        }
+
+        if commits.len() == 1 {
+            return Err(Error::invalid("Need at least 2 commits to squash"));
+        }
+
+        let (result, stash_warning) = self.rewrite(OperationKind::Squash, auto_stash, |repo, progress| {
+            rewrite::squash_commits_impl(repo, commits, new_message, progress)
+        })?;
+        Ok(RewriteResult { stash_warning, ..result })
+    }
+
+    /// Remove `commits` from the history. Stops on the first commit that
+    /// does not apply anymore, see [`Rewriter::continue_rewrite`].
+    pub fn drop_commits(&mut self, commits: &[Oid], auto_stash: AutoStash) -> Result<RewriteResult, Error> {
+        if commits.is_empty() {
+            return Err(Error::invalid("No commits selected"));
+        }
+
+        self.stoppable_rewrite(OperationKind::Drop, auto_stash, |repo, stash, progress| {
+            rewrite::drop_commits_impl(repo, commits, stash, progress)
+        })
+    }
+
+    /// Split `commit` into one commit per group of hunks, in order.
+    pub fn split(&mut self, commit: Oid, groups: &[SplitGroup], auto_stash: AutoStash) -> Result<RewriteResult, Error> {
+        let (result, stash_warning) = self.rewrite(OperationKind::Split, auto_stash, |repo, progress| {
+            rewrite::split_commit_impl(repo, commit, groups, progress)
+        })?;
+        Ok(RewriteResult { stash_warning, ..result })
+    }
+
+    /// Change the author and/or committer of `commits`.
+    pub fn edit_identity(&mut self, commits: &[Oid], edit: &IdentityEdit, auto_stash: AutoStash) -> Result<RewriteResult, Error> {
+        if commits.is_empty() {
+            return Err(Error::invalid("No commits selected"));
+        }
+        if edit.is_empty() {
+            return Err(Error::invalid("No author or committer change given"));
+        }
+
+        let (result, stash_warning) = self.rewrite(OperationKind::EditIdentity, auto_stash, |repo, progress| {
+            rewrite::rewrite_commit_identity(repo, commits, edit, progress)
+        })?;
+        Ok(RewriteResult { stash_warning, ..result })
+    }
+
+    /// Map every author and committer reachable from HEAD, or from every
+    /// local branch and tag with `all_refs`, through `mailmap`.
+    pub fn apply_mailmap(&mut self, mailmap: &str, all_refs: bool, auto_stash: AutoStash) -> Result<MailmapResult, Error> {
+        let (result, stash_warning) = self.rewrite(OperationKind::Mailmap, auto_stash, |repo, progress| {
+            identity::rewrite_with_mailmap(repo, mailmap, all_refs, progress)
+        })?;
+        Ok(MailmapResult { stash_warning, ..result })
+    }
+
+    /// Run an interactive rebase plan. Stops on edit steps and conflicts,
+    /// see [`Rewriter::continue_rewrite`].
+    pub fn execute_plan(&mut self, steps: &[RebaseStep], auto_stash: AutoStash) -> Result<RewriteResult, Error> {
+        self.stoppable_rewrite(OperationKind::RebasePlan, auto_stash, |repo, stash, progress| {
+            rebase::start(repo, steps, stash, progress)
+        })
+    }
+
+    /// Go on with the stopped rewrite once its conflicts are resolved.
+    pub fn continue_rewrite(&mut self) -> Result<RewriteResult, Error> {
+        let stash = rebase::auto_stashed(&self.repo)?;
+        let result = rebase::resume(&self.repo, &self.progress)?;
+        self.finish_stopped(result, stash)
+    }
+
+    /// Leave out the commit the rewrite stopped on, and go on.
+    pub fn skip_step(&mut self) -> Result<RewriteResult, Error> {
+        let stash = rebase::auto_stashed(&self.repo)?;
+        let result = rebase::skip(&self.repo, &self.progress)?;
+        self.finish_stopped(result, stash)
+    }
+
+    /// Put every ref back where it was before the stopped rewrite. Returns a
+    /// warning when auto-stashed changes could not be restored.
+    pub fn abort(&mut self) -> Result<Option<String>, Error> {
+        let stash_warning = rebase::abort(&self.repo)?.and_then(|stash| stash::restore(&mut self.repo, stash));
+        oplog::discard_pending(&self.repo)?;
+        Ok(stash_warning)
+    }
+
+    /// Undo the last operation of the operation log.
+    pub fn undo(&self) -> Result<Operation, Error> {
+        self.move_refs(oplog::undo)
+    }
+
+    /// Redo the last undone operation.
+    pub fn redo(&self) -> Result<Operation, Error> {
+        self.move_refs(oplog::redo)
+    }
+
+    /// Move the ref saved in the backup `backup_name` back. Returns the name
+    /// of the restored ref.
+    pub fn restore_backup(&self, backup_name: &str) -> Result<String, Error> {
+        let before = oplog::begin(&self.repo)?;
+        let restored = self.move_refs(|repo| backup::restore(repo, backup_name));
+        match &restored {
+            Ok(_) => oplog::record(&self.repo, OperationKind::RestoreBackup, &before)?,
+            Err(_) => oplog::release(&self.repo, &before)?,
+        }
+        restored
+    }
+
+    /// Delete backups older than `max_age_days`. Returns their names.
+    pub fn prune_backups(&self, max_age_days: u64) -> Result<Vec<String>, Error> {
+        backup::prune(&self.repo, max_age_days, oplog::unix_now())
+    }
+
+    /// Check out the local branch `branch_name`. Returns a warning when
+    /// auto-stashed changes could not be restored on it.
+    pub fn switch_branch(&mut self, branch_name: &str, auto_stash: AutoStash) -> Result<Option<String>, Error> {
+        // A stopped rewrite goes on from the detached HEAD it left
+        rebase::ensure_not_in_progress(&self.repo)?;
+
+        // Handle uncommitted changes
+        if auto_stash == AutoStash::Off {
+            repo::check_working_directory_clean(&self.repo)?;
+        }
+        let stash = auto_stash.save(&mut self.repo)?;
+
+        repo::switch_branch(&self.repo, branch_name)?;
+
+        // Restore stashed changes; a stash that does not apply is kept
+        Ok(stash.and_then(|stash| stash::restore(&mut self.repo, stash)))
+    }
+
+    /// Run the rewrite `f` on a history not being rewritten already, with
+    /// local changes stashed if asked, and record it in the operation log.
+    /// Returns its result with the warning of a stash that did not apply.
+    fn rewrite<T>(
+        &mut self,
+        kind: OperationKind,
+        auto_stash: AutoStash,
+        f: impl FnOnce(&Repository, &Progress) -> Result<T, Error>,
+    ) -> Result<(T, Option<String>), Error> {
+        rebase::ensure_not_in_progress(&self.repo)?;
+
+        // Local changes can stay: the working directory is only updated when the
+        // tree of HEAD changes, and never overwritten
+        let stash = auto_stash.save(&mut self.repo)?;
+
+        let before = oplog::begin(&self.repo)?;
+        let result = f(&self.repo, &self.progress);
+
+        // Restore stashed changes if we stashed them; a stash that does not apply is kept
+        let stash_warning = stash.and_then(|stash| stash::restore(&mut self.repo, stash));
+
+        match &result {
+            Ok(_) => oplog::record(&self.repo, kind, &before)?,
+            Err(_) => oplog::release(&self.repo, &before)?,
+        }
+
+        result.map(|result| (result, stash_warning))
+    }
+
+    /// Like [`Rewriter::rewrite`], for the rewrites that check out commits
+    /// one by one and may stop on one: `f` is given the auto-stash to keep
+    /// while stopped.
+    fn stoppable_rewrite(
+        &mut self,
+        kind: OperationKind,
+        auto_stash: AutoStash,
+        f: impl FnOnce(&Repository, Option<Oid>, &Progress) -> Result<RewriteResult, Error>,
+    ) -> Result<RewriteResult, Error> {
+        rebase::ensure_not_in_progress(&self.repo)?;
+
+        // Check for uncommitted changes
+        if auto_stash == AutoStash::Off {
+            repo::check_working_directory_clean(&self.repo)?;
+        }
+        let stash = auto_stash.save(&mut self.repo)?;
+
+        let before = oplog::begin(&self.repo)?;
+        let result = f(&self.repo, stash, &self.progress);
+
+        // Stashed changes stay put while the rewrite is stopped on an edit step or a conflict
+        let stopped = matches!(&result, Ok(r) if r.paused_at.is_some());
+        let stash_warning = stash.filter(|_| !stopped).and_then(|stash| stash::restore(&mut self.repo, stash));
+
+        // A stopped rewrite is recorded once it is continued to the end
+        match &result {
+            Ok(r) if r.paused_at.is_some() => oplog::begin_pending(&self.repo, kind, before)?,
+            Ok(_) => oplog::record(&self.repo, kind, &before)?,
+            Err(_) => oplog::release(&self.repo, &before)?,
+        }
+
+        result.map(|result| RewriteResult { stash_warning, ..result })
+    }
+
+    /// Restore the auto-stash and record the operation once a continued
+    /// rewrite reached the end.
+    fn finish_stopped(&mut self, mut result: RewriteResult, stash: Option<Oid>) -> Result<RewriteResult, Error> {
+        if result.paused_at.is_none() {
+            result.stash_warning = stash.and_then(|stash| stash::restore(&mut self.repo, stash));
+            oplog::finish_pending(&self.repo)?;
+        }
+        Ok(result)
+    }
+
+    /// Run `f`, which moves refs, on a clean worktree not being rewritten,
+    /// then check out the new HEAD.
+    fn move_refs<T>(&self, f: impl FnOnce(&Repository) -> Result<T, Error>) -> Result<T, Error> {
+        rebase::ensure_not_in_progress(&self.repo)?;
+        repo::check_working_directory_clean(&self.repo)?;
+
+        let result = f(&self.repo)?;
+        repo::checkout_head(&self.repo)?;
+        Ok(result)
+    }
+}
@@ -0,0 +1,205 @@
+use crate::error::Error;
+use git2::build::TreeUpdateBuilder;
+use git2::{Commit, Delta, DiffHunk, FileMode, Oid, Patch, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A hunk of a commit's diff, identified by its file and its header as
+/// returned by `get_commit_diff` (`@@ -1,3 +1,4 @@`).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SplitHunk {
+    pub path: String,
+    pub header: String,
+}
+
+/// Changes that make up one of the commits a commit is split into.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SplitGroup {
+    /// Files whose whole change goes into this commit
+    pub paths: Vec<String>,
+    /// Single hunks of text files going into this commit
+    pub hunks: Vec<SplitHunk>,
+    pub message: String,
+}
+
+/// Header of a hunk, in the format shown by `get_commit_diff`.
+pub(crate) fn hunk_header(hunk: &DiffHunk) -> String {
+    format!(
+        "@@ -{},{} +{},{} @@",
+        hunk.old_start(),
+        hunk.old_lines(),
+        hunk.new_start(),
+        hunk.new_lines()
+    )
+}
+
+/// One file changed by the commit being split.
+struct FileChange<'repo> {
+    status: Delta,
+    old_id: Oid,
+    new_id: Oid,
+    new_mode: FileMode,
+    patch: Option<Patch<'repo>>,
+    headers: Vec<String>,
+}
+
+/// Which part of a file's change has been given to a group so far.
+enum Selection {
+    Whole,
+    Hunks(Vec<usize>),
+}
+
+/// Trees of the commits `commit` is split into, one per group.
+///
+/// Each tree is the parent tree with the changes of this group and of all
+/// previous groups applied. Changes that no group selects go into the last
+/// commit, whose tree is always the tree of `commit`, so descendants are
+/// left untouched.
+pub(crate) fn piece_trees(repo: &Repository, commit: &Commit, groups: &[SplitGroup]) -> Result<Vec<Oid>, Error> {
+    let commit_tree = commit.tree().map_err(|e| Error::git("Failed to get commit tree", e))?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => parent.tree().map_err(|e| Error::git("Failed to get parent tree", e))?,
+        Err(_) => {
+            let oid = repo.treebuilder(None)
+                .and_then(|builder| builder.write())
+                .map_err(|e| Error::git("Failed to write empty tree", e))?;
+            repo.find_tree(oid).map_err(|e| Error::git("Failed to find tree", e))?
+        }
+    };
+
+    // Same diff as `get_commit_diff`, so paths and hunk headers match
+    let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&commit_tree), None)
+        .map_err(|e| Error::git("Failed to create diff", e))?;
+
+    let mut changes: HashMap<String, FileChange> = HashMap::new();
+    for (delta_idx, delta) in diff.deltas().enumerate() {
+        let file = if delta.status() == Delta::Deleted { delta.old_file() } else { delta.new_file() };
+        let path = file.path()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let patch = Patch::from_diff(&diff, delta_idx)
+            .map_err(|e| Error::git("Failed to create patch", e))?;
+        let mut headers = Vec::new();
+        if let Some(patch) = &patch {
+            for hunk_idx in 0..patch.num_hunks() {
+                let (hunk, _) = patch.hunk(hunk_idx).map_err(|e| Error::git("Failed to read hunk", e))?;
+                headers.push(hunk_header(&hunk));
+            }
+        }
+
+        changes.insert(path, FileChange {
+            status: delta.status(),
+            old_id: delta.old_file().id(),
+            new_id: delta.new_file().id(),
+            new_mode: delta.new_file().mode(),
+            patch,
+            headers,
+        });
+    }
+
+    let short_hash = &commit.id().to_string()[..7];
+    let not_changed = |path: &str| Error::invalid(format!("File {} is not changed by commit {}", path, short_hash));
+    let selected_twice = |path: &str| Error::invalid(format!("Changes of {} are selected by more than one group", path));
+
+    let mut selections: HashMap<String, Selection> = HashMap::new();
+    let mut trees = Vec::with_capacity(groups.len());
+    let mut previous_tree = parent_tree.id();
+
+    for (group_idx, group) in groups.iter().enumerate() {
+        for path in &group.paths {
+            if !changes.contains_key(path) {
+                return Err(not_changed(path));
+            }
+            if selections.insert(path.clone(), Selection::Whole).is_some() {
+                return Err(selected_twice(path));
+            }
+        }
+
+        for hunk in &group.hunks {
+            let change = changes.get(&hunk.path).ok_or_else(|| not_changed(&hunk.path))?;
+            let hunk_idx = change.headers.iter().position(|header| *header == hunk.header)
+                .ok_or_else(|| Error::invalid(format!("Hunk {} not found in {}", hunk.header, hunk.path)))?;
+
+            match selections.entry(hunk.path.clone()).or_insert_with(|| Selection::Hunks(Vec::new())) {
+                Selection::Hunks(hunks) if !hunks.contains(&hunk_idx) => hunks.push(hunk_idx),
+                _ => return Err(selected_twice(&hunk.path)),
+            }
+        }
+
+        let tree = if group_idx + 1 == groups.len() {
+            commit_tree.id()
+        } else {
+            let mut builder = TreeUpdateBuilder::new();
+            for (path, selection) in &selections {
+                let change = &changes[path];
+                let whole = match selection {
+                    Selection::Whole => true,
+                    Selection::Hunks(hunks) => hunks.len() == change.headers.len(),
+                };
+
+                if whole && change.status == Delta::Deleted {
+                    builder.remove(path);
+                } else if whole {
+                    builder.upsert(path, change.new_id, change.new_mode);
+                } else if let (Selection::Hunks(hunks), Some(patch)) = (selection, &change.patch) {
+                    let old_content = if change.status == Delta::Added {
+                        Vec::new()
+                    } else {
+                        repo.find_blob(change.old_id)
+                            .map_err(|e| Error::git("Failed to find blob", e))?
+                            .content()
+                            .to_vec()
+                    };
+                    let content = apply_hunks(&old_content, patch, hunks)?;
+                    let blob = repo.blob(&content).map_err(|e| Error::git("Failed to write blob", e))?;
+                    builder.upsert(path, blob, change.new_mode);
+                }
+            }
+            builder.create_updated(repo, &parent_tree)
+                .map_err(|e| Error::git("Failed to write tree", e))?
+        };
+
+        if tree == previous_tree {
+            return Err(Error::invalid(format!("Group {} of the split has no changes", group_idx + 1)));
+        }
+        previous_tree = tree;
+        trees.push(tree);
+    }
+
+    Ok(trees)
+}
+
+/// `old` with only the selected hunks of `patch` applied.
+fn apply_hunks(old: &[u8], patch: &Patch, hunks: &[usize]) -> Result<Vec<u8>, Error> {
+    let old_lines: Vec<&[u8]> = old.split_inclusive(|byte| *byte == b'\n').collect();
+    let mut hunks = hunks.to_vec();
+    hunks.sort_unstable();
+
+    let mut content = Vec::with_capacity(old.len());
+    let mut next_line = 0;
+    for hunk_idx in hunks {
+        let (hunk, line_count) = patch.hunk(hunk_idx).map_err(|e| Error::git("Failed to read hunk", e))?;
+
+        // A hunk that only adds lines starts after `old_start` instead of at it
+        let start = if hunk.old_lines() == 0 { hunk.old_start() } else { hunk.old_start() - 1 } as usize;
+        for line in old_lines.iter().take(start).skip(next_line) {
+            content.extend_from_slice(line);
+        }
+
+        for line_idx in 0..line_count {
+            let line = patch.line_in_hunk(hunk_idx, line_idx)
+                .map_err(|e| Error::git("Failed to read hunk line", e))?;
+            if matches!(line.origin(), ' ' | '+') {
+                content.extend_from_slice(line.content());
+            }
+        }
+
+        next_line = start + hunk.old_lines() as usize;
+    }
+    for line in old_lines.iter().skip(next_line) {
+        content.extend_from_slice(line);
+    }
+
+    Ok(content)
+}
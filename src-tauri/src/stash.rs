@@ -0,0 +1,101 @@
+use crate::error::Error;
+use git2::{ErrorCode, Index, MergeOptions, Oid, Repository, StashFlags};
+
+const MESSAGE: &str = "git-rewrite-auto-stash";
+
+/// Stash local changes, and untracked files too with `include_untracked`.
+/// Returns the stash commit, `None` when there was nothing to stash.
+pub(crate) fn save(repo: &mut Repository, include_untracked: bool) -> Result<Option<Oid>, Error> {
+    let signature = repo.signature()
+        .map_err(|e| Error::git("Failed to get signature", e))?;
+
+    let flags = if include_untracked { StashFlags::INCLUDE_UNTRACKED } else { StashFlags::DEFAULT };
+    match repo.stash_save(&signature, MESSAGE, Some(flags)) {
+        Ok(oid) => Ok(Some(oid)),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(Error::git("Failed to stash", e)),
+    }
+}
+
+/// Position of the stash commit `stash` in the stash list.
+fn stash_index(repo: &mut Repository, stash: Oid) -> Option<usize> {
+    let mut found = None;
+    let _ = repo.stash_foreach(|index, _, oid| {
+        if *oid == stash {
+            found = Some(index);
+        }
+        found.is_none()
+    });
+    found
+}
+
+/// Put the auto-stash `stash` back into the working directory and drop it.
+///
+/// Like `git stash pop`, the stash is kept in the stash list when it does not
+/// apply cleanly: either nothing is touched, or the conflicts are left in the
+/// index to resolve. The returned warning says which and where the changes
+/// are kept. Nothing is returned when the changes were restored.
+pub(crate) fn restore(repo: &mut Repository, stash: Oid) -> Option<String> {
+    let short = &stash.to_string()[..7];
+    let Some(index) = stash_index(repo, stash) else {
+        return Some(format!(
+            "The auto-stash {} is no longer in the stash list, so local changes were not restored. Recover them with `git stash apply {}`.",
+            short, stash
+        ));
+    };
+
+    if let Err(e) = repo.stash_apply(index, None) {
+        let reason = match e.code() {
+            ErrorCode::Conflict | ErrorCode::MergeConflict => match merge_conflicts(repo, stash) {
+                Ok(paths) if !paths.is_empty() => format!("they conflict with the rewritten history in {}", paths.join(", ")),
+                _ => "they conflict with the rewritten history".to_string(),
+            },
+            _ => e.message().to_string(),
+        };
+        return Some(format!(
+            "Local changes could not be restored: {}. They are kept in stash@{{{}}} ({}).",
+            reason, index, short
+        ));
+    }
+
+    let conflicts = repo.index()
+        .and_then(|mut repo_index| {
+            repo_index.read(false)?;
+            conflicted_paths(&repo_index)
+        })
+        .unwrap_or_default();
+    if !conflicts.is_empty() {
+        return Some(format!(
+            "Local changes conflict with the rewritten history in {}. Resolve the conflicts, then drop stash@{{{}}} ({}).",
+            conflicts.join(", "), index, short
+        ));
+    }
+
+    if let Err(e) = repo.stash_drop(index) {
+        return Some(format!(
+            "Local changes were restored but the auto-stash stash@{{{}}} ({}) could not be dropped: {}",
+            index, short, e
+        ));
+    }
+    None
+}
+
+/// Paths that conflict when merging the stash onto HEAD.
+fn merge_conflicts(repo: &Repository, stash: Oid) -> Result<Vec<String>, git2::Error> {
+    let stash_commit = repo.find_commit(stash)?;
+    let base = stash_commit.parent(0)?.tree()?;
+    let head = repo.head()?.peel_to_tree()?;
+    let index = repo.merge_trees(&base, &head, &stash_commit.tree()?, Some(&MergeOptions::new()))?;
+    conflicted_paths(&index)
+}
+
+fn conflicted_paths(index: &Index) -> Result<Vec<String>, git2::Error> {
+    let mut paths = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            paths.push(String::from_utf8_lossy(&entry.path).to_string());
+        }
+    }
+    Ok(paths)
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::Fixture;
+use git2::Status;
+use git_rewrite_lib::{AutoStash, ErrorKind};
+
+/// Three commits `A`, `B` (adds `b.txt`), `C`, with `a.txt` modified and
+/// `notes.txt` untracked in the working directory.
+fn dirty_fixture() -> (Fixture, [git2::Oid; 3]) {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.write("a.txt", "a, edited\n");
+    fixture.write("notes.txt", "notes\n");
+    (fixture, [a, b, c])
+}
+
+#[test]
+fn restores_tracked_changes_after_a_rewrite() {
+    let (mut fixture, [_, b, _]) = dirty_fixture();
+
+    let result = fixture.rewriter().edit_message(b, "B2", AutoStash::Tracked).unwrap();
+
+    assert_eq!(result.stash_warning, None);
+    assert_eq!(fixture.log(), ["C", "B2", "A"]);
+    assert_eq!(fixture.status(), [
+        ("a.txt".to_string(), Status::WT_MODIFIED),
+        ("notes.txt".to_string(), Status::WT_NEW),
+    ]);
+    assert_eq!(fixture.read("a.txt"), b"a, edited\n");
+    assert_eq!(fixture.stash_count(), 0);
+}
+
+#[test]
+fn stashes_untracked_files_on_request() {
+    let (mut fixture, [_, b, _]) = dirty_fixture();
+
+    let result = fixture.rewriter().drop_commits(&[b], AutoStash::IncludeUntracked).unwrap();
+
+    assert_eq!(result.stash_warning, None);
+    assert_eq!(fixture.log(), ["C", "A"]);
+    assert!(!fixture.path().join("b.txt").exists());
+    assert_eq!(fixture.read("a.txt"), b"a, edited\n");
+    assert_eq!(fixture.read("notes.txt"), b"notes\n");
+    assert_eq!(fixture.stash_count(), 0);
+}
+
+#[test]
+fn refuses_to_check_out_over_local_changes_without_auto_stash() {
+    let (fixture, [_, b, c]) = dirty_fixture();
+
+    let error = fixture.rewriter().drop_commits(&[b], AutoStash::Off).unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::DirtyWorktree));
+    assert_eq!(fixture.head(), c);
+    assert_eq!(fixture.read("a.txt"), b"a, edited\n");
+}
+
+#[test]
+fn leaves_local_changes_alone_when_the_tree_does_not_change() {
+    let (fixture, [_, b, _]) = dirty_fixture();
+
+    fixture.rewriter().edit_message(b, "B2", AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["C", "B2", "A"]);
+    assert_eq!(fixture.read("a.txt"), b"a, edited\n");
+    assert_eq!(fixture.read("notes.txt"), b"notes\n");
+}
+
+#[test]
+fn keeps_the_stash_when_it_conflicts_with_the_new_history() {
+    let (mut fixture, [_, b, _]) = dirty_fixture();
+    fixture.write("b.txt", "b, edited\n");
+
+    let result = fixture.rewriter().drop_commits(&[b], AutoStash::Tracked).unwrap();
+
+    assert_eq!(fixture.log(), ["C", "A"]);
+    let warning = result.stash_warning.expect("a warning about the stash");
+    assert!(warning.contains("b.txt"), "{}", warning);
+    assert!(warning.contains("stash@{0}"), "{}", warning);
+    assert_eq!(fixture.stash_count(), 1);
+}
+
+#[test]
+fn leaves_older_stashes_in_place() {
+    let (mut fixture, [_, b, _]) = dirty_fixture();
+    let older = fixture.stash("older work");
+    fixture.write("c.txt", "c, edited\n");
+
+    fixture.rewriter().edit_message(b, "B2", AutoStash::Tracked).unwrap();
+
+    assert_eq!(fixture.read("c.txt"), b"c, edited\n");
+    assert_eq!(fixture.stash_count(), 1);
+    let mut stashes = Vec::new();
+    fixture.repo.stash_foreach(|_, _, oid| {
+        stashes.push(*oid);
+        true
+    }).unwrap();
+    assert_eq!(stashes, [older]);
+}
+
+#[test]
+fn keeps_the_stash_while_a_rewrite_is_stopped() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let x1 = fixture.commit_files(&[("x.txt", "1\n")], "X1");
+    fixture.commit_files(&[("x.txt", "2\n")], "X2");
+    fixture.write("a.txt", "a, edited\n");
+
+    // X2 changes a file X1 added: dropping X1 stops on X2
+    let result = fixture.rewriter().drop_commits(&[x1], AutoStash::Tracked).unwrap();
+    assert!(result.paused_at.is_some());
+    assert_eq!(fixture.stash_count(), 1);
+    assert_eq!(fixture.read("a.txt"), b"a\n");
+
+    fixture.write("x.txt", "2\n");
+    fixture.stage("x.txt");
+    let result = fixture.rewriter().continue_rewrite().unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(result.stash_warning, None);
+    assert_eq!(fixture.log(), ["X2", "A"]);
+    assert_eq!(fixture.read("a.txt"), b"a, edited\n");
+    assert_eq!(fixture.stash_count(), 0);
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::{step, Fixture};
+use git_rewrite_lib::{AutoStash, RebaseAction};
+
+const OLD_BACKUP: &str = "refs/git-rewrite/backup/heads/main/1000";
+
+#[test]
+fn prunes_backups_older_than_the_given_age() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.reference(OLD_BACKUP, a);
+    let rewriter = fixture.rewriter();
+
+    assert_eq!(rewriter.prune_backups(36_500 * 100).unwrap(), Vec::<String>::new());
+    assert_eq!(rewriter.backups().unwrap().len(), 1);
+
+    assert_eq!(rewriter.prune_backups(1).unwrap(), [OLD_BACKUP]);
+    assert!(rewriter.backups().unwrap().is_empty());
+}
+
+#[test]
+fn keeps_every_backup_when_the_age_overflows() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.reference(OLD_BACKUP, a);
+    let rewriter = fixture.rewriter();
+
+    assert_eq!(rewriter.prune_backups(u64::MAX).unwrap(), Vec::<String>::new());
+    assert_eq!(rewriter.prune_backups(u64::MAX / (24 * 60 * 60)).unwrap(), Vec::<String>::new());
+    assert_eq!(rewriter.backups().unwrap().len(), 1);
+}
+
+#[test]
+fn backs_up_rewritten_branches_and_restores_them() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.rewriter().edit_message(b, "B, reworded", AutoStash::Off).unwrap();
+    assert_eq!(fixture.log(), ["B, reworded", "A"]);
+
+    let rewriter = fixture.rewriter();
+    let backups = rewriter.backups().unwrap();
+    let [backup] = &backups[..] else { panic!("expected one backup, got {:?}", backups) };
+    assert_eq!(backup.source, "refs/heads/main");
+    assert_eq!(backup.hash, b.to_string());
+    assert_eq!(backup.summary, "B");
+
+    assert_eq!(rewriter.restore_backup(&backup.name).unwrap(), "refs/heads/main");
+
+    assert_eq!(fixture.branch_target("main"), b);
+    assert_eq!(fixture.log(), ["B", "A"]);
+}
+
+#[test]
+fn refuses_to_restore_a_ref_that_is_not_a_backup() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.branch("other", a);
+
+    assert!(fixture.rewriter().restore_backup("refs/heads/other").is_err());
+    assert_eq!(fixture.head(), a);
+}
+
+#[test]
+fn backs_up_refs_before_moving_them() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.tag("v1", a);
+    let steps = [step(a, RebaseAction::Edit), step(b, RebaseAction::Pick)];
+
+    // Stopped with no ref moved yet, every old target is already kept
+    fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+    let mut sources: Vec<String> = fixture.rewriter().backups().unwrap().into_iter().map(|backup| backup.source).collect();
+    sources.sort();
+    assert_eq!(sources, ["refs/heads/main", "refs/tags/v1"]);
+
+    fixture.rewriter().abort().unwrap();
+    assert!(fixture.rewriter().backups().unwrap().is_empty());
+}
+
+#[test]
+fn keeps_only_the_backups_of_moved_refs() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.tag("v1", a);
+    fixture.rewriter().edit_message(b, "B, reworded", AutoStash::Off).unwrap();
+
+    let backups = fixture.rewriter().backups().unwrap();
+    assert_eq!(backups.iter().map(|backup| backup.source.as_str()).collect::<Vec<_>>(), ["refs/heads/main"]);
+    assert_eq!(fixture.rewriter().operations().unwrap()[0].backups, [backups[0].name.clone()]);
+}
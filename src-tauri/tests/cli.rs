@@ -0,0 +1,43 @@
+mod common;
+
+use common::Fixture;
+use std::process::{Command, Output};
+
+fn cli(fixture: &Fixture, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_git-rewrite-cli"))
+        .arg("--repo")
+        .arg(fixture.path())
+        .args(args)
+        .output()
+        .expect("run git-rewrite-cli")
+}
+
+#[test]
+fn resolves_abbreviated_hashes_and_revisions() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.commit_files(&[("b.txt", "b\n")], "B");
+
+    let output = cli(&fixture, &["edit-commit-message", &a.to_string()[..7], "A, reworded"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fixture.log(), ["B", "A, reworded"]);
+
+    let output = cli(&fixture, &["get-commit-diff", "HEAD~1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(diff["message"], "A, reworded");
+}
+
+#[test]
+fn rejects_a_revision_that_names_no_commit() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+
+    let output = cli(&fixture, &["edit-commit-message", "abc1234", "Reworded"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(error["kind"], "invalid_request");
+    assert!(error["message"].as_str().unwrap().contains("'abc1234'"), "{}", error);
+    assert_eq!(fixture.head(), a);
+}
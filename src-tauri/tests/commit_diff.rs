@@ -0,0 +1,92 @@
+mod common;
+
+use common::Fixture;
+
+#[test]
+fn lists_added_and_deleted_lines() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "one\ntwo\nthree\n")], "A");
+    let b = fixture.commit_files(&[("a.txt", "one\n2\nthree\nfour\n")], "B");
+
+    let diff = fixture.rewriter().commit_diff(b).unwrap();
+
+    assert_eq!(diff.hash, b.to_string());
+    assert_eq!(diff.message, "B");
+    assert_eq!(diff.author, "Test User");
+    assert_eq!((diff.stats.files_changed, diff.stats.insertions, diff.stats.deletions), (1, 2, 1));
+
+    let [file] = &diff.files[..] else { panic!("expected one file, got {:?}", diff.files) };
+    assert_eq!((file.path.as_str(), file.status.as_str()), ("a.txt", "Modified"));
+    assert_eq!((file.insertions, file.deletions), (2, 1));
+    let lines: Vec<(&str, &str)> = file.lines.iter().map(|line| (line.line_type.as_str(), line.content.as_str())).collect();
+    assert_eq!(lines, [
+        ("header", "@@ -1,3 +1,4 @@"),
+        ("context", "one\n"),
+        ("delete", "two\n"),
+        ("add", "2\n"),
+        ("context", "three\n"),
+        ("add", "four\n"),
+    ]);
+    let deleted = &file.lines[2];
+    assert_eq!((deleted.old_line_no, deleted.new_line_no), (Some(2), None));
+    let added = &file.lines[5];
+    assert_eq!((added.old_line_no, added.new_line_no), (None, Some(4)));
+}
+
+#[test]
+fn diffs_the_initial_commit_against_an_empty_tree() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n"), ("dir/b.txt", "b\n")], "A");
+
+    let diff = fixture.rewriter().commit_diff(a).unwrap();
+
+    let files: Vec<(&str, &str)> = diff.files.iter().map(|file| (file.path.as_str(), file.status.as_str())).collect();
+    assert_eq!(files, [("a.txt", "Added"), ("dir/b.txt", "Added")]);
+    assert_eq!(diff.stats.insertions, 2);
+}
+
+#[test]
+fn reports_deleted_and_binary_files() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("gone.txt", "gone\n")], "A");
+    fixture.remove("gone.txt");
+    fixture.write("image.bin", [0u8, 1, 2, 0, 255]);
+    let b = fixture.commit("B");
+
+    let diff = fixture.rewriter().commit_diff(b).unwrap();
+
+    let files: Vec<(&str, &str)> = diff.files.iter().map(|file| (file.path.as_str(), file.status.as_str())).collect();
+    assert_eq!(files, [("gone.txt", "Deleted"), ("image.bin", "Added")]);
+    let binary = &diff.files[1];
+    assert!(binary.lines.is_empty());
+    assert_eq!((binary.insertions, binary.deletions), (0, 0));
+    assert_eq!(diff.files[0].deletions, 1);
+}
+
+#[test]
+fn diffs_a_merge_against_its_first_parent() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.branch("feature", a);
+    fixture.commit_files(&[("main.txt", "main\n")], "Main");
+    fixture.checkout("feature");
+    let f = fixture.commit_files(&[("feature.txt", "feature\n")], "Feature");
+    fixture.checkout("main");
+    fixture.write("feature.txt", "feature\n");
+    let merge = fixture.merge(f, "Merge feature");
+
+    let diff = fixture.rewriter().commit_diff(merge).unwrap();
+
+    let files: Vec<&str> = diff.files.iter().map(|file| file.path.as_str()).collect();
+    assert_eq!(files, ["feature.txt"]);
+}
+
+#[test]
+fn fails_on_an_unknown_commit() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let missing = git2::Oid::hash_object(git2::ObjectType::Blob, b"not a commit").unwrap();
+
+    assert!(fixture.rewriter().commit_diff(missing).is_err());
+    assert!(fixture.rewriter().commit_diff(a).is_ok());
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::Fixture;
+use git2::Oid;
+use git_rewrite_lib::{ErrorKind, RefSelector};
+
+/// `main` with A, B; `feature` with F on A; `origin/main` with R on B; a tag
+/// `v1` on T, a commit only reachable from the tag.
+fn fixture_with_refs() -> (Fixture, [Oid; 5]) {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.branch("feature", a);
+    fixture.checkout("feature");
+    let f = fixture.commit_files(&[("f.txt", "f\n")], "F");
+    fixture.checkout("main");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let r = fixture.commit_files(&[("r.txt", "r\n")], "R");
+    fixture.reference("refs/remotes/origin/main", r);
+    let t = fixture.commit_files(&[("t.txt", "t\n")], "T");
+    fixture.annotated_tag("v1", t, "Release 1");
+    fixture.reference("refs/heads/main", b);
+    fixture.checkout("main");
+    (fixture, [a, b, f, r, t])
+}
+
+fn messages(fixture: &Fixture, refs: &[RefSelector]) -> Vec<String> {
+    let page = fixture.rewriter().commits(refs, 0, 100).unwrap();
+    assert_eq!(page.total_count, page.commits.len());
+    page.commits.into_iter().map(|commit| commit.message).collect()
+}
+
+#[test]
+fn lists_head_by_default() {
+    let (fixture, _) = fixture_with_refs();
+
+    assert_eq!(messages(&fixture, &[]), ["B", "A"]);
+    assert_eq!(messages(&fixture, &[RefSelector::Head]), ["B", "A"]);
+}
+
+#[test]
+fn lists_branches_remotes_and_tags() {
+    let (fixture, _) = fixture_with_refs();
+
+    assert_eq!(messages(&fixture, &[RefSelector::Branch("feature".into())]), ["F", "A"]);
+    assert_eq!(messages(&fixture, &[RefSelector::AllBranches]), ["B", "F", "A"]);
+    assert_eq!(messages(&fixture, &[RefSelector::AllRemotes]), ["R", "B", "A"]);
+    assert_eq!(messages(&fixture, &[RefSelector::Tags]), ["T", "R", "B", "A"]);
+    assert_eq!(
+        messages(&fixture, &[RefSelector::AllBranches, RefSelector::AllRemotes, RefSelector::Tags]),
+        ["T", "R", "B", "F", "A"],
+    );
+}
+
+#[test]
+fn lists_refs_matching_a_glob() {
+    let (fixture, _) = fixture_with_refs();
+
+    assert_eq!(messages(&fixture, &[RefSelector::Glob("heads/feat*".into())]), ["F", "A"]);
+    assert_eq!(messages(&fixture, &[RefSelector::Glob("refs/remotes/origin".into())]), ["R", "B", "A"]);
+    assert_eq!(messages(&fixture, &[RefSelector::Glob("heads/nothing-*".into())]), Vec::<String>::new());
+}
+
+#[test]
+fn keeps_parents_in_the_listed_history() {
+    let (fixture, [a, b, f, ..]) = fixture_with_refs();
+
+    let page = fixture.rewriter().commits(&[RefSelector::AllBranches], 0, 100).unwrap();
+
+    let parents: Vec<(String, Vec<String>)> = page.commits.into_iter().map(|commit| (commit.hash, commit.parent_ids)).collect();
+    assert_eq!(parents, [
+        (b.to_string(), vec![a.to_string()]),
+        (f.to_string(), vec![a.to_string()]),
+        (a.to_string(), vec![]),
+    ]);
+}
+
+#[test]
+fn searches_and_pages_the_selected_history() {
+    let (fixture, [.., t]) = fixture_with_refs();
+    let mut rewriter = fixture.rewriter();
+    let all = [RefSelector::AllBranches, RefSelector::Tags];
+    let t_prefix = &t.to_string()[..10];
+
+    let found = rewriter.search_commits(&all, t_prefix, 0, 10).unwrap();
+    assert_eq!(found.commits.iter().map(|commit| commit.message.as_str()).collect::<Vec<_>>(), ["T"]);
+    assert_eq!(rewriter.search_commits(&[], t_prefix, 0, 10).unwrap().total_count, 0);
+
+    let page = rewriter.commits(&all, 1, 2).unwrap();
+    assert_eq!(page.commits.iter().map(|commit| commit.message.as_str()).collect::<Vec<_>>(), ["R", "B"]);
+    assert!(page.has_more);
+    assert_eq!(page.total_count, 5);
+
+    // Going back to HEAD after another selection lists HEAD again
+    assert_eq!(rewriter.commits(&[], 0, 10).unwrap().total_count, 2);
+}
+
+#[test]
+fn refuses_an_unknown_branch() {
+    let (fixture, _) = fixture_with_refs();
+
+    let error = fixture.rewriter().commits(&[RefSelector::Branch("missing".into())], 0, 10).unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::InvalidRequest), "{:?}", error);
+    assert!(error.message.contains("missing"), "{}", error.message);
+}
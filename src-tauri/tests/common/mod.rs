@@ -0,0 +1,205 @@
+//! Temporary repositories built commit by commit, for the integration tests.
+
+#![allow(dead_code)]
+
+use git2::{BranchType, Commit, ObjectType, Oid, Repository, Signature, Status, StatusOptions, Time, TreeWalkMode, TreeWalkResult};
+use git_rewrite_lib::{RebaseAction, RebaseStep, Rewriter};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// A repository in a temporary directory, on branch `main`, removed when
+/// dropped. Commits get increasing timestamps, so that their order does not
+/// depend on how fast the test runs.
+pub struct Fixture {
+    dir: TempDir,
+    pub repo: Repository,
+    clock: i64,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        let dir = TempDir::new().expect("create temporary directory");
+        let repo = Repository::init(dir.path()).expect("init repository");
+        repo.set_head("refs/heads/main").expect("point HEAD to main");
+        let mut config = repo.config().expect("open config");
+        config.set_str("user.name", "Test User").expect("set user.name");
+        config.set_str("user.email", "test@example.com").expect("set user.email");
+        Fixture { dir, repo, clock: 1_600_000_000 }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn rewriter(&self) -> Rewriter {
+        Rewriter::open(self.path()).expect("open rewriter")
+    }
+
+    /// Write `content` to `path` in the working directory.
+    pub fn write(&self, path: &str, content: impl AsRef<[u8]>) {
+        let path = self.path().join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("create directories");
+        }
+        fs::write(path, content).expect("write file");
+    }
+
+    pub fn read(&self, path: &str) -> Vec<u8> {
+        fs::read(self.path().join(path)).expect("read file")
+    }
+
+    pub fn remove(&self, path: &str) {
+        fs::remove_file(self.path().join(path)).expect("remove file");
+    }
+
+    pub fn rename(&self, from: &str, to: &str) {
+        fs::rename(self.path().join(from), self.path().join(to)).expect("rename file");
+    }
+
+    /// Add `path` to the index, like `git add` after resolving a conflict.
+    pub fn stage(&self, path: &str) {
+        let mut index = self.repo.index().expect("open index");
+        index.add_path(Path::new(path)).expect("add file");
+        index.write().expect("write index");
+    }
+
+    /// Commit the whole working directory on HEAD, authored by Test User.
+    pub fn commit(&mut self, message: &str) -> Oid {
+        self.commit_as("Test User", "test@example.com", message)
+    }
+
+    pub fn commit_as(&mut self, name: &str, email: &str, message: &str) -> Oid {
+        let parents: Vec<Oid> = self.repo.head().ok().and_then(|head| head.target()).into_iter().collect();
+        self.commit_with_parents(name, email, message, &parents)
+    }
+
+    /// Write `files` and commit them on HEAD.
+    pub fn commit_files(&mut self, files: &[(&str, &str)], message: &str) -> Oid {
+        for (path, content) in files {
+            self.write(path, content);
+        }
+        self.commit(message)
+    }
+
+    /// Commit the working directory on HEAD with `other` as second parent,
+    /// like a merge whose conflicts were resolved by hand.
+    pub fn merge(&mut self, other: Oid, message: &str) -> Oid {
+        let head = self.head();
+        self.commit_with_parents("Test User", "test@example.com", message, &[head, other])
+    }
+
+    fn commit_with_parents(&mut self, name: &str, email: &str, message: &str, parents: &[Oid]) -> Oid {
+        let mut index = self.repo.index().expect("open index");
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).expect("add files");
+        index.update_all(["*"], None).expect("update index");
+        index.write().expect("write index");
+        let tree = self.repo.find_tree(index.write_tree().expect("write tree")).expect("find tree");
+
+        self.clock += 60;
+        let signature = Signature::new(name, email, &Time::new(self.clock, 0)).expect("signature");
+        let parents: Vec<Commit> = parents.iter().map(|oid| self.commit_of(*oid)).collect();
+        let parents: Vec<&Commit> = parents.iter().collect();
+        self.repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents).expect("commit")
+    }
+
+    /// Create branch `name` at `target`.
+    pub fn branch(&self, name: &str, target: Oid) {
+        self.repo.branch(name, &self.commit_of(target), false).expect("create branch");
+    }
+
+    pub fn tag(&self, name: &str, target: Oid) {
+        self.repo.tag_lightweight(name, self.commit_of(target).as_object(), false).expect("create tag");
+    }
+
+    pub fn annotated_tag(&self, name: &str, target: Oid, message: &str) {
+        let tagger = Signature::new("Test User", "test@example.com", &Time::new(self.clock, 0)).expect("signature");
+        self.repo.tag(name, self.commit_of(target).as_object(), &tagger, message, false).expect("create tag");
+    }
+
+    /// Create or move the ref `name` (e.g. `refs/remotes/origin/main`) to `target`.
+    pub fn reference(&self, name: &str, target: Oid) {
+        self.repo.reference(name, target, true, "test fixture").expect("create reference");
+    }
+
+    /// Check out branch `name`, overwriting local changes.
+    pub fn checkout(&self, name: &str) {
+        self.repo.set_head(&format!("refs/heads/{}", name)).expect("set HEAD");
+        self.repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).expect("checkout");
+    }
+
+    pub fn head(&self) -> Oid {
+        self.repo.head().expect("HEAD").target().expect("HEAD target")
+    }
+
+    pub fn branch_target(&self, name: &str) -> Oid {
+        self.repo.find_branch(name, BranchType::Local).expect("find branch").get().target().expect("branch target")
+    }
+
+    pub fn tag_target(&self, name: &str) -> Oid {
+        self.repo.revparse_single(&format!("refs/tags/{}", name)).expect("find tag").peel_to_commit().expect("tagged commit").id()
+    }
+
+    pub fn commit_of(&self, oid: Oid) -> Commit<'_> {
+        self.repo.find_commit(oid).expect("find commit")
+    }
+
+    pub fn message(&self, oid: Oid) -> String {
+        self.commit_of(oid).message().expect("utf-8 message").to_string()
+    }
+
+    /// Messages from HEAD following first parents, newest first.
+    pub fn log(&self) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut commit = Some(self.commit_of(self.head()));
+        while let Some(current) = commit {
+            messages.push(current.message().expect("utf-8 message").to_string());
+            commit = current.parent(0).ok();
+        }
+        messages
+    }
+
+    /// Every file of the tree of `oid`, with its content.
+    pub fn files(&self, oid: Oid) -> BTreeMap<String, Vec<u8>> {
+        let tree = self.commit_of(oid).tree().expect("tree");
+        let mut files = BTreeMap::new();
+        tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(ObjectType::Blob) {
+                let blob = self.repo.find_blob(entry.id()).expect("find blob");
+                files.insert(format!("{}{}", dir, entry.name().expect("utf-8 path")), blob.content().to_vec());
+            }
+            TreeWalkResult::Ok
+        }).expect("walk tree");
+        files
+    }
+
+    /// Local changes, untracked files included, as `(path, status)` sorted by path.
+    pub fn status(&self) -> Vec<(String, Status)> {
+        let mut options = StatusOptions::new();
+        options.include_untracked(true);
+        let statuses = self.repo.statuses(Some(&mut options)).expect("status");
+        statuses.iter().map(|entry| (entry.path().expect("utf-8 path").to_string(), entry.status())).collect()
+    }
+
+    /// Stash local changes to tracked files, like `git stash`.
+    pub fn stash(&mut self, message: &str) -> Oid {
+        let signature = Signature::now("Test User", "test@example.com").expect("signature");
+        self.repo.stash_save(&signature, message, None).expect("stash")
+    }
+
+    /// Number of entries in the stash list.
+    pub fn stash_count(&mut self) -> usize {
+        let mut count = 0;
+        self.repo.stash_foreach(|_, _, _| {
+            count += 1;
+            true
+        }).expect("list stashes");
+        count
+    }
+}
+
+/// Step of a rebase plan applying `action` to `oid`, with no message.
+pub fn step(oid: Oid, action: RebaseAction) -> RebaseStep {
+    RebaseStep { hash: oid.to_string(), action, message: None }
+}
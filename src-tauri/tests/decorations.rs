@@ -0,0 +1,111 @@
+mod common;
+
+use common::Fixture;
+use git2::Oid;
+use git_rewrite_lib::{Decoration, DecorationKind, RefSelector};
+
+fn decoration(name: &str, kind: DecorationKind, is_head: bool) -> Decoration {
+    Decoration { name: name.to_string(), kind, is_head }
+}
+
+/// Decorations of each listed commit, by message.
+fn decorations(fixture: &Fixture, refs: &[RefSelector]) -> Vec<(String, Vec<Decoration>)> {
+    let page = fixture.rewriter().commits(refs, 0, 100).unwrap();
+    page.commits.into_iter().map(|commit| (commit.message, commit.decorations)).collect()
+}
+
+/// `main` with A, B, C; `feature` and a lightweight tag `v0` on A; an
+/// annotated tag `v1` and `origin/main` on B.
+fn decorated_fixture() -> (Fixture, [Oid; 3]) {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.branch("feature", a);
+    fixture.tag("v0", a);
+    fixture.annotated_tag("v1", b, "Release 1");
+    fixture.reference("refs/remotes/origin/main", b);
+    (fixture, [a, b, c])
+}
+
+#[test]
+fn decorates_commits_with_branches_remotes_and_tags() {
+    let (fixture, _) = decorated_fixture();
+
+    assert_eq!(decorations(&fixture, &[]), [
+        ("C".to_string(), vec![decoration("main", DecorationKind::Branch, true)]),
+        ("B".to_string(), vec![
+            decoration("origin/main", DecorationKind::RemoteBranch, false),
+            decoration("v1", DecorationKind::Tag, false),
+        ]),
+        ("A".to_string(), vec![
+            decoration("feature", DecorationKind::Branch, false),
+            decoration("v0", DecorationKind::Tag, false),
+        ]),
+    ]);
+}
+
+#[test]
+fn decorates_a_detached_head() {
+    let (fixture, [_, b, _]) = decorated_fixture();
+    fixture.repo.set_head_detached(b).unwrap();
+
+    let listed = decorations(&fixture, &[RefSelector::AllBranches]);
+
+    assert_eq!(listed[0], ("C".to_string(), vec![decoration("main", DecorationKind::Branch, false)]));
+    assert_eq!(listed[1].1, [
+        decoration("HEAD", DecorationKind::Head, true),
+        decoration("origin/main", DecorationKind::RemoteBranch, false),
+        decoration("v1", DecorationKind::Tag, false),
+    ]);
+}
+
+#[test]
+fn decorates_the_stash_and_leaves_other_refs_out() {
+    let (mut fixture, [a, _, c]) = decorated_fixture();
+    fixture.reference("refs/git-rewrite/backup/heads/main/1600000000", a);
+    fixture.write("c.txt", "c, edited\n");
+    let stash = fixture.stash("work in progress");
+
+    // A glob without `*`, `?` or `[` would get a trailing `/*`
+    let page = fixture.rewriter().commits(&[RefSelector::Glob("stas[h]".into())], 0, 100).unwrap();
+
+    let stashed = page.commits.iter().find(|commit| commit.hash == stash.to_string()).expect("the stash commit");
+    assert_eq!(stashed.decorations, [decoration("stash", DecorationKind::Stash, false)]);
+    let head = page.commits.iter().find(|commit| commit.hash == c.to_string()).expect("the HEAD commit");
+    assert_eq!(head.decorations, [decoration("main", DecorationKind::Branch, true)]);
+    let root = page.commits.iter().find(|commit| commit.hash == a.to_string()).expect("the root commit");
+    assert_eq!(root.decorations.len(), 2, "{:?}", root.decorations);
+}
+
+#[test]
+fn updates_decorations_when_refs_move() {
+    let (fixture, [a, _, c]) = decorated_fixture();
+    let mut rewriter = fixture.rewriter();
+    assert_eq!(rewriter.commits(&[], 0, 1).unwrap().commits[0].decorations.len(), 1);
+
+    fixture.branch("topic", c);
+    fixture.reference("refs/heads/feature", c);
+
+    let page = rewriter.commits(&[], 0, 10).unwrap();
+    assert_eq!(page.commits[0].decorations, [
+        decoration("main", DecorationKind::Branch, true),
+        decoration("feature", DecorationKind::Branch, false),
+        decoration("topic", DecorationKind::Branch, false),
+    ]);
+    assert_eq!(page.commits[2].hash, a.to_string());
+    assert_eq!(page.commits[2].decorations, [decoration("v0", DecorationKind::Tag, false)]);
+}
+
+#[test]
+fn decorates_search_results() {
+    let (fixture, _) = decorated_fixture();
+
+    let found = fixture.rewriter().search_commits(&[], "B", 0, 10).unwrap();
+
+    let [commit] = &found.commits[..] else { panic!("expected one commit, got {:?}", found.commits) };
+    assert_eq!(commit.decorations, [
+        decoration("origin/main", DecorationKind::RemoteBranch, false),
+        decoration("v1", DecorationKind::Tag, false),
+    ]);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::Fixture;
+use git2::Oid;
+use git_rewrite_lib::AutoStash;
+
+/// `feature` with F1 (adds `f1.txt`) and F2 (adds `f2.txt`) forked from A,
+/// `main` with M, then `main` merging `feature`. The merge also edits
+/// `a.txt`, like a conflict resolved by hand would.
+fn merged_fixture() -> (Fixture, [Oid; 4]) {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.branch("feature", a);
+    fixture.checkout("feature");
+    let f1 = fixture.commit_files(&[("f1.txt", "f1\n")], "F1");
+    let f2 = fixture.commit_files(&[("f2.txt", "f2\n")], "F2");
+    fixture.checkout("main");
+    fixture.commit_files(&[("m.txt", "m\n")], "M");
+    fixture.write("f1.txt", "f1\n");
+    fixture.write("f2.txt", "f2\n");
+    fixture.write("a.txt", "a, merged\n");
+    let merge = fixture.merge(f2, "Merge feature");
+    (fixture, [a, f1, f2, merge])
+}
+
+#[test]
+fn drops_a_commit_and_replays_the_ones_after_it() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.commit_files(&[("c.txt", "c\n")], "C");
+
+    let result = fixture.rewriter().drop_commits(&[b], AutoStash::Off).unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(fixture.log(), ["C", "A"]);
+    assert_eq!(fixture.files(fixture.head()).into_keys().collect::<Vec<_>>(), ["a.txt", "c.txt"]);
+    assert!(!fixture.path().join("b.txt").exists());
+}
+
+#[test]
+fn drops_a_side_branch_commit_below_a_merge() {
+    let (fixture, [_, f1, _, _]) = merged_fixture();
+
+    let result = fixture.rewriter().drop_commits(&[f1], AutoStash::Off).unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(fixture.log(), ["Merge feature", "M", "A"]);
+    let files = fixture.files(fixture.head());
+    assert_eq!(files.keys().collect::<Vec<_>>(), ["a.txt", "f2.txt", "m.txt"]);
+    // What the merge itself changed is kept
+    assert_eq!(files["a.txt"], b"a, merged\n");
+    assert!(!fixture.path().join("f1.txt").exists());
+
+    let feature = fixture.branch_target("feature");
+    assert_eq!(fixture.message(feature), "F2");
+    assert_eq!(fixture.commit_of(fixture.head()).parent_id(1).unwrap(), feature);
+}
+
+#[test]
+fn stops_on_a_merge_that_conflicts_once_redone() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.branch("feature", a);
+    fixture.checkout("feature");
+    let f1 = fixture.commit_files(&[("f1.txt", "f1\n")], "F1");
+    let f2 = fixture.commit_files(&[("a.txt", "f2\n")], "F2");
+    fixture.checkout("main");
+    fixture.commit_files(&[("a.txt", "m\n")], "M");
+    fixture.write("a.txt", "resolved\n");
+    fixture.write("f1.txt", "f1\n");
+    let merge = fixture.merge(f2, "Merge feature");
+
+    let result = fixture.rewriter().drop_commits(&[f1], AutoStash::Off).unwrap();
+
+    assert_eq!(result.paused_at, Some(merge.to_string()));
+    assert_eq!(result.conflicted_commits, [merge.to_string()]);
+    let conflicts = fixture.rewriter().rewrite_conflicts().unwrap();
+    assert_eq!(conflicts.iter().map(|conflict| conflict.path.as_str()).collect::<Vec<_>>(), ["a.txt"]);
+    assert!(!fixture.path().join("f1.txt").exists());
+
+    fixture.write("a.txt", "resolved again\n");
+    fixture.stage("a.txt");
+    let result = fixture.rewriter().continue_rewrite().unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(fixture.log(), ["Merge feature", "M", "A"]);
+    let files = fixture.files(fixture.head());
+    assert_eq!(files.keys().collect::<Vec<_>>(), ["a.txt"]);
+    assert_eq!(files["a.txt"], b"resolved again\n");
+    assert_eq!(fixture.commit_of(fixture.head()).parent_id(1).unwrap(), fixture.branch_target("feature"));
+}
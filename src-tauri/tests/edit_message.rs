@@ -0,0 +1,127 @@
+mod common;
+
+use common::Fixture;
+use git_rewrite_lib::{AutoStash, ErrorKind};
+
+#[test]
+fn rewords_a_commit_and_replays_its_descendants() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+
+    let result = fixture.rewriter().edit_message(b, "B reworded", AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["C", "B reworded", "A"]);
+    assert_eq!(result.new_hash, fixture.head().to_string());
+    assert_eq!(result.updated_branches, ["main"]);
+    assert_eq!(fixture.branch_target("main"), fixture.head());
+
+    // Trees, authors and dates are kept
+    let new_c = fixture.head();
+    let new_b = fixture.commit_of(new_c).parent_id(0).unwrap();
+    assert_ne!(new_c, c);
+    assert_eq!(fixture.files(new_c), fixture.files(c));
+    assert_eq!(fixture.files(new_b), fixture.files(b));
+    for (old, new) in [(b, new_b), (c, new_c)] {
+        let (old, new) = (fixture.commit_of(old), fixture.commit_of(new));
+        assert_eq!(old.author().name(), new.author().name());
+        assert_eq!(old.author().when(), new.author().when());
+        assert_eq!(old.committer().when(), new.committer().when());
+    }
+}
+
+#[test]
+fn rewords_head_and_the_initial_commit() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+
+    fixture.rewriter().edit_message(b, "B2", AutoStash::Off).unwrap();
+    assert_eq!(fixture.log(), ["B2", "A"]);
+    assert_eq!(fixture.commit_of(fixture.head()).parent_id(0).unwrap(), a);
+
+    fixture.rewriter().edit_message(a, "A2", AutoStash::Off).unwrap();
+    assert_eq!(fixture.log(), ["B2", "A2"]);
+    let root = fixture.commit_of(fixture.head()).parent(0).unwrap();
+    assert_eq!(root.parent_count(), 0);
+    assert_eq!(fixture.files(fixture.head()), fixture.files(b));
+}
+
+#[test]
+fn keeps_merges_when_rewording_below_them() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.branch("feature", b);
+    fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.checkout("feature");
+    let f = fixture.commit_files(&[("f.txt", "f\n")], "F");
+    fixture.checkout("main");
+    fixture.write("f.txt", "f\n");
+    let merge = fixture.merge(f, "Merge feature");
+    fixture.commit_files(&[("d.txt", "d\n")], "D");
+
+    fixture.rewriter().edit_message(b, "B2", AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["D", "Merge feature", "C", "B2", "A"]);
+    let new_merge = fixture.commit_of(fixture.head()).parent(0).unwrap();
+    assert_eq!(new_merge.parent_count(), 2);
+    assert_eq!(fixture.files(new_merge.id()), fixture.files(merge));
+
+    // The side branch is replayed onto B2, and its branch follows
+    let new_f = new_merge.parent_id(1).unwrap();
+    assert_eq!(fixture.branch_target("feature"), new_f);
+    assert_eq!(fixture.message(fixture.commit_of(new_f).parent_id(0).unwrap()), "B2");
+}
+
+#[test]
+fn moves_branches_and_tags_on_rewritten_commits_only() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.tag("v1", b);
+    fixture.branch("at-b", b);
+    fixture.branch("at-a", a);
+
+    let result = fixture.rewriter().edit_message(b, "B2", AutoStash::Off).unwrap();
+
+    let mut updated = result.updated_branches.clone();
+    updated.sort();
+    assert_eq!(updated, ["at-b", "main", "v1"]);
+    let new_b = fixture.commit_of(fixture.head()).parent_id(0).unwrap();
+    assert_eq!(fixture.branch_target("at-b"), new_b);
+    assert_eq!(fixture.tag_target("v1"), new_b);
+    assert_eq!(fixture.branch_target("at-a"), a);
+}
+
+#[test]
+fn refuses_a_commit_outside_the_current_branch() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.branch("other", a);
+    fixture.checkout("other");
+    let side = fixture.commit_files(&[("side.txt", "side\n")], "Side");
+    fixture.checkout("main");
+    let head = fixture.commit_files(&[("b.txt", "b\n")], "B");
+
+    let error = fixture.rewriter().edit_message(side, "Side2", AutoStash::Off).unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::CommitNotInBranch { ref branch } if branch == "main"));
+    assert_eq!(fixture.head(), head);
+    assert_eq!(fixture.branch_target("other"), side);
+}
+
+#[test]
+fn can_be_undone() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+
+    fixture.rewriter().edit_message(b, "B2", AutoStash::Off).unwrap();
+    fixture.rewriter().undo().unwrap();
+
+    assert_eq!(fixture.head(), b);
+    assert_eq!(fixture.log(), ["B", "A"]);
+}
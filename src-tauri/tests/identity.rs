@@ -0,0 +1,67 @@
+mod common;
+
+use common::Fixture;
+use git_rewrite_lib::{AutoStash, IdentityEdit};
+
+#[test]
+fn edits_the_author_of_selected_commits() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.tag("v1", b);
+    let edit = IdentityEdit {
+        author_name: Some("New Name".to_string()),
+        author_email: Some("new@example.com".to_string()),
+        ..IdentityEdit::default()
+    };
+
+    fixture.rewriter().edit_identity(&[b], &edit, AutoStash::Off).unwrap();
+
+    let tagged = fixture.commit_of(fixture.tag_target("v1"));
+    assert_eq!(tagged.message(), Some("B"));
+    assert_eq!(tagged.author().name(), Some("New Name"));
+    assert_eq!(tagged.author().email(), Some("new@example.com"));
+    // The committer and the author time are kept
+    assert_eq!(tagged.committer().name(), Some("Test User"));
+    assert_eq!(tagged.author().when(), fixture.commit_of(b).author().when());
+    assert_eq!(tagged.parent_id(0).unwrap(), a);
+
+    let head = fixture.commit_of(fixture.head());
+    assert_eq!(head.parent_id(0).unwrap(), tagged.id());
+    assert_eq!(head.author().name(), Some("Test User"));
+}
+
+#[test]
+fn refuses_an_empty_identity_edit() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+
+    assert!(fixture.rewriter().edit_identity(&[a], &IdentityEdit::default(), AutoStash::Off).is_err());
+    assert_eq!(fixture.head(), a);
+}
+
+#[test]
+fn applies_a_mailmap_and_keeps_unchanged_commits() {
+    let mut fixture = Fixture::new();
+    fixture.write("a.txt", "a\n");
+    let a = fixture.commit_as("Someone", "someone@example.com", "A");
+    fixture.write("b.txt", "b\n");
+    let b = fixture.commit_as("Old Name", "old@example.com", "B");
+    fixture.write("c.txt", "c\n");
+    fixture.commit_as("Someone", "someone@example.com", "C");
+    fixture.branch("topic", b);
+
+    let result = fixture.rewriter()
+        .apply_mailmap("New Name <new@example.com> Old Name <old@example.com>\n", true, AutoStash::Off)
+        .unwrap();
+
+    assert_eq!(result.changed_commits, 1);
+    assert_eq!(result.updated_refs, ["main", "topic"]);
+    let topic = fixture.commit_of(fixture.branch_target("topic"));
+    assert_eq!(topic.author().name(), Some("New Name"));
+    assert_eq!(topic.committer().email(), Some("new@example.com"));
+    assert_eq!(topic.parent_id(0).unwrap(), a);
+    assert_eq!(fixture.commit_of(fixture.head()).author().name(), Some("Someone"));
+    assert_eq!(fixture.log(), ["C", "B", "A"]);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{step, Fixture};
+use git2::Oid;
+use git_rewrite_lib::RebaseAction;
+
+#[test]
+fn previews_a_plan_without_moving_refs() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.tag("v1", c);
+    fixture.branch("base", a);
+
+    let preview = fixture.rewriter()
+        .preview_plan(&[step(a, RebaseAction::Pick), step(b, RebaseAction::Drop), step(c, RebaseAction::Pick)])
+        .unwrap();
+
+    let mapping: Vec<(String, Option<String>)> = preview.mapping.iter()
+        .map(|entry| (entry.old_hash.clone(), entry.new_hash.clone()))
+        .collect();
+    let new_c = preview.new_head.clone().expect("a new HEAD");
+    assert_eq!(mapping, [
+        (a.to_string(), Some(a.to_string())),
+        (b.to_string(), None),
+        (c.to_string(), Some(new_c.clone())),
+    ]);
+    assert_eq!(preview.changed_commits, [b.to_string(), c.to_string()]);
+    assert_eq!(preview.affected_refs, ["main", "v1"]);
+    assert!(preview.conflicts.is_empty());
+
+    let new_c = fixture.commit_of(Oid::from_str(&new_c).unwrap());
+    assert_eq!(new_c.parent_id(0).unwrap(), a);
+    assert_eq!(fixture.head(), c);
+    assert_eq!(fixture.status(), []);
+}
+
+#[test]
+fn previews_a_squash() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+
+    let preview = fixture.rewriter().preview_squash(&[b, c], "B and C").unwrap();
+
+    let new_head = Oid::from_str(preview.new_head.as_deref().unwrap()).unwrap();
+    assert_eq!(fixture.message(new_head), "B and C");
+    assert_eq!(fixture.commit_of(new_head).parent_id(0).unwrap(), a);
+    assert_eq!(preview.changed_commits, [b.to_string(), c.to_string()]);
+    assert_eq!(fixture.head(), c);
+}
+
+#[test]
+fn lists_the_commits_that_would_conflict() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("a.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("a.txt", "c\n")], "C");
+
+    let preview = fixture.rewriter()
+        .preview_plan(&[step(a, RebaseAction::Pick), step(b, RebaseAction::Drop), step(c, RebaseAction::Pick)])
+        .unwrap();
+
+    assert_eq!(preview.conflicts, [c.to_string()]);
+    assert_eq!(preview.mapping[2].new_hash, None);
+    assert_eq!(preview.new_head, Some(a.to_string()));
+    assert_eq!(fixture.head(), c);
+    assert!(!fixture.repo.head_detached().unwrap());
+}
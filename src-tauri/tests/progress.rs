@@ -0,0 +1,70 @@
+mod common;
+
+use common::Fixture;
+use git_rewrite_lib::{AutoStash, CancelToken, ErrorKind, IdentityEdit, Progress};
+use std::sync::{Arc, Mutex};
+
+fn fixture_with_commits(count: usize) -> Fixture {
+    let mut fixture = Fixture::new();
+    for n in 0..count {
+        fixture.commit_files(&[(&format!("{}.txt", n), "content\n")], &format!("Commit {}", n));
+    }
+    fixture
+}
+
+#[test]
+fn reports_progress_up_to_the_total() {
+    let fixture = fixture_with_commits(5);
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    let mut rewriter = fixture.rewriter()
+        .with_progress(Progress::new(move |done, total| sink.lock().unwrap().push((done, total))));
+
+    rewriter.apply_mailmap("New Name <new@example.com> Test User <test@example.com>\n", false, AutoStash::Off).unwrap();
+
+    let reports = reports.lock().unwrap();
+    assert!(!reports.is_empty());
+    assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0), "{:?}", reports);
+    let (done, total) = *reports.last().unwrap();
+    assert_eq!(done, total);
+}
+
+#[test]
+fn leaves_refs_untouched_when_cancelled() {
+    let fixture = fixture_with_commits(5);
+    let head = fixture.head();
+    let cancel = CancelToken::default();
+    let on_report = cancel.clone();
+    // Cancel as soon as the first commit is rewritten
+    let progress = Progress::new(move |_, _| on_report.cancel()).with_cancel(cancel);
+    let edit = IdentityEdit { author_name: Some("New Name".to_string()), ..IdentityEdit::default() };
+    let all: Vec<_> = fixture.repo.revwalk()
+        .and_then(|mut walk| walk.push_head().map(|_| walk))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+
+    let error = fixture.rewriter().with_progress(progress).edit_identity(&all, &edit, AutoStash::Off).unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::Cancelled), "{:?}", error);
+    assert_eq!(fixture.head(), head);
+    assert!(fixture.rewriter().backups().unwrap().is_empty());
+    assert_eq!(fixture.commit_of(head).author().name(), Some("Test User"));
+}
+
+#[test]
+fn refuses_to_start_once_cancelled() {
+    let fixture = fixture_with_commits(2);
+    let head = fixture.head();
+    let cancel = CancelToken::default();
+    cancel.cancel();
+
+    let error = fixture.rewriter()
+        .with_progress(Progress::default().with_cancel(cancel))
+        .edit_message(head, "Reworded", AutoStash::Off)
+        .unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::Cancelled), "{:?}", error);
+    assert_eq!(fixture.head(), head);
+    assert_eq!(fixture.log(), ["Commit 1", "Commit 0"]);
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use common::{step, Fixture};
+use git2::Oid;
+use git_rewrite_lib::{AutoStash, ErrorKind, RebaseAction, RebaseStep};
+
+/// Three commits `A`, `B`, `C`, each adding its own file.
+fn linear_fixture() -> (Fixture, [Oid; 3]) {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+    (fixture, [a, b, c])
+}
+
+#[test]
+fn refuses_short_hashes() {
+    let (fixture, [_, b, c]) = linear_fixture();
+    let steps = [
+        RebaseStep { hash: "abc".to_string(), action: RebaseAction::Reword, message: None },
+        step(b, RebaseAction::Pick),
+        step(c, RebaseAction::Pick),
+    ];
+
+    let error = fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::InvalidRequest), "{:?}", error);
+    assert!(error.message.contains("'abc'"), "{}", error.message);
+    assert_eq!(fixture.head(), c);
+}
+
+#[test]
+fn stops_on_edit_and_amends_on_continue() {
+    let (fixture, [a, b, c]) = linear_fixture();
+    let steps = [step(a, RebaseAction::Pick), step(b, RebaseAction::Edit), step(c, RebaseAction::Pick)];
+
+    let result = fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+    assert_eq!(result.paused_at, Some(b.to_string()));
+    assert!(fixture.repo.head_detached().unwrap());
+    assert_eq!(fixture.branch_target("main"), c);
+
+    fixture.write("b.txt", "b, amended\n");
+    let result = fixture.rewriter().continue_rewrite().unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(fixture.log(), ["C", "B", "A"]);
+    assert_eq!(fixture.files(fixture.head())["b.txt"], b"b, amended\n");
+    assert!(!fixture.repo.head_detached().unwrap());
+}
+
+#[test]
+fn refuses_to_switch_branch_while_stopped() {
+    let (fixture, [a, b, c]) = linear_fixture();
+    fixture.branch("other", a);
+    let steps = [step(a, RebaseAction::Pick), step(b, RebaseAction::Edit), step(c, RebaseAction::Pick)];
+    fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+
+    let error = fixture.rewriter().switch_branch("other", AutoStash::Off).unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::RewriteInProgress), "{:?}", error);
+    assert!(fixture.repo.head_detached().unwrap());
+}
+
+#[test]
+fn refuses_to_continue_once_head_moved() {
+    let (fixture, [a, b, c]) = linear_fixture();
+    fixture.branch("other", a);
+    let steps = [step(a, RebaseAction::Pick), step(b, RebaseAction::Edit), step(c, RebaseAction::Pick)];
+    fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+
+    // Switching outside of the app
+    fixture.checkout("other");
+    let error = fixture.rewriter().continue_rewrite().unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::InvalidRequest), "{:?}", error);
+    assert_eq!(fixture.branch_target("main"), c);
+
+    fixture.rewriter().abort().unwrap();
+    assert_eq!(fixture.log(), ["C", "B", "A"]);
+}
+
+#[test]
+fn fails_with_a_conflict_when_replaying_onto_an_empty_history() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("a.txt", "b\n")], "B");
+
+    // B changes a file that no longer exists without A
+    let error = fixture.rewriter()
+        .execute_plan(&[step(a, RebaseAction::Drop), step(b, RebaseAction::Pick)], AutoStash::Off)
+        .unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::Conflict { .. }), "{:?}", error);
+    assert_eq!(fixture.head(), b);
+    assert!(!fixture.repo.head_detached().unwrap());
+}
+
+#[test]
+fn rewords_and_fixes_up_in_one_plan() {
+    let (fixture, [a, b, c]) = linear_fixture();
+    let steps = [
+        step(a, RebaseAction::Pick),
+        RebaseStep { hash: b.to_string(), action: RebaseAction::Reword, message: Some("B, reworded".to_string()) },
+        step(c, RebaseAction::Fixup),
+    ];
+
+    let result = fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(fixture.log(), ["B, reworded", "A"]);
+    assert_eq!(fixture.files(fixture.head()).into_keys().collect::<Vec<_>>(), ["a.txt", "b.txt", "c.txt"]);
+    assert_eq!(fixture.commit_of(fixture.head()).parent_id(0).unwrap(), a);
+}
+
+#[test]
+fn squashes_with_the_combined_message() {
+    let (fixture, [a, b, c]) = linear_fixture();
+    let steps = [step(a, RebaseAction::Pick), step(b, RebaseAction::Pick), step(c, RebaseAction::Squash)];
+
+    fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["B\n\nC", "A"]);
+}
+
+/// `A` adds `a.txt`, `B` and `C` each change it in turn.
+fn conflicting_fixture() -> (Fixture, [Oid; 3]) {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("a.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("a.txt", "c\n")], "C");
+    (fixture, [a, b, c])
+}
+
+#[test]
+fn stops_on_a_conflict_and_continues_once_resolved() {
+    let (fixture, [a, b, c]) = conflicting_fixture();
+    let steps = [step(a, RebaseAction::Pick), step(c, RebaseAction::Pick), step(b, RebaseAction::Pick)];
+
+    let result = fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+
+    assert_eq!(result.paused_at, Some(c.to_string()));
+    assert_eq!(result.conflicted_commits, [c.to_string()]);
+    let conflicts = fixture.rewriter().rewrite_conflicts().unwrap();
+    let [conflict] = &conflicts[..] else { panic!("expected one conflict, got {:?}", conflicts) };
+    assert_eq!(conflict.path, "a.txt");
+    assert_eq!(conflict.theirs.as_ref().unwrap().content, "c\n");
+
+    // Without resolving, continuing fails and keeps the rewrite stopped
+    assert!(fixture.rewriter().continue_rewrite().is_err());
+
+    fixture.write("a.txt", "c\n");
+    fixture.stage("a.txt");
+    let result = fixture.rewriter().continue_rewrite().unwrap();
+
+    // B then conflicts with the resolved C
+    assert_eq!(result.paused_at, Some(b.to_string()));
+    fixture.write("a.txt", "b\n");
+    fixture.stage("a.txt");
+    let result = fixture.rewriter().continue_rewrite().unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(fixture.log(), ["B", "C", "A"]);
+    assert_eq!(fixture.read("a.txt"), b"b\n");
+}
+
+#[test]
+fn skips_a_conflicting_step() {
+    let (fixture, [a, b, c]) = conflicting_fixture();
+    let steps = [step(a, RebaseAction::Pick), step(c, RebaseAction::Pick), step(b, RebaseAction::Pick)];
+    fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+
+    let result = fixture.rewriter().skip_step().unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(fixture.log(), ["B", "A"]);
+    assert_eq!(fixture.read("a.txt"), b"b\n");
+    assert!(!fixture.repo.head_detached().unwrap());
+}
+
+#[test]
+fn aborts_a_stopped_rewrite() {
+    let (fixture, [a, b, c]) = conflicting_fixture();
+    let steps = [step(a, RebaseAction::Pick), step(c, RebaseAction::Pick), step(b, RebaseAction::Pick)];
+    fixture.rewriter().execute_plan(&steps, AutoStash::Off).unwrap();
+
+    fixture.rewriter().abort().unwrap();
+
+    assert_eq!(fixture.head(), c);
+    assert!(!fixture.repo.head_detached().unwrap());
+    assert_eq!(fixture.read("a.txt"), b"c\n");
+    assert!(fixture.rewriter().rewrite_conflicts().is_err());
+}
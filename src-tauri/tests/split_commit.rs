@@ -0,0 +1,64 @@
+mod common;
+
+use common::Fixture;
+use git_rewrite_lib::{AutoStash, SplitGroup, SplitHunk};
+
+fn group(paths: &[&str], hunks: Vec<SplitHunk>, message: &str) -> SplitGroup {
+    SplitGroup {
+        paths: paths.iter().map(|path| path.to_string()).collect(),
+        hunks,
+        message: message.to_string(),
+    }
+}
+
+#[test]
+fn splits_a_commit_by_file() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let both = fixture.commit_files(&[("x.txt", "x\n"), ("y.txt", "y\n")], "X and Y");
+    fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.branch("topic", both);
+    let original_tree = fixture.commit_of(both).tree_id();
+
+    let result = fixture.rewriter()
+        .split(both, &[group(&["x.txt"], vec![], "X"), group(&["y.txt"], vec![], "Y")], AutoStash::Off)
+        .unwrap();
+
+    assert_eq!(result.paused_at, None);
+    assert_eq!(fixture.log(), ["C", "Y", "X", "A"]);
+    let topic = fixture.branch_target("topic");
+    assert_eq!(fixture.message(topic), "Y");
+    assert_eq!(fixture.commit_of(topic).tree_id(), original_tree);
+    let x = fixture.commit_of(topic).parent_id(0).unwrap();
+    assert_eq!(fixture.files(x).into_keys().collect::<Vec<_>>(), ["a.txt", "x.txt"]);
+}
+
+#[test]
+fn splits_the_hunks_of_one_file() {
+    let mut fixture = Fixture::new();
+    let lines: Vec<String> = (1..=12).map(|n| format!("line {}\n", n)).collect();
+    fixture.commit_files(&[("a.txt", &lines.concat())], "A");
+    let mut edited = lines.clone();
+    edited[0] = "line 1, edited\n".to_string();
+    edited[11] = "line 12, edited\n".to_string();
+    let both = fixture.commit_files(&[("a.txt", &edited.concat())], "Edit both ends");
+
+    let diff = fixture.rewriter().commit_diff(both).unwrap();
+    let headers: Vec<String> = diff.files[0].lines.iter()
+        .filter(|line| line.line_type == "header")
+        .map(|line| line.content.clone())
+        .collect();
+    assert_eq!(headers.len(), 2, "{:?}", headers);
+    let hunk = SplitHunk { path: "a.txt".to_string(), header: headers[1].clone() };
+
+    fixture.rewriter()
+        .split(both, &[group(&[], vec![hunk], "Edit the end"), group(&[], vec![], "Edit the start")], AutoStash::Off)
+        .unwrap();
+
+    assert_eq!(fixture.log(), ["Edit the start", "Edit the end", "A"]);
+    let first = fixture.commit_of(fixture.head()).parent_id(0).unwrap();
+    let mut expected = lines;
+    expected[11] = "line 12, edited\n".to_string();
+    assert_eq!(fixture.files(first)["a.txt"], expected.concat().into_bytes());
+    assert_eq!(fixture.files(fixture.head())["a.txt"], edited.concat().into_bytes());
+}
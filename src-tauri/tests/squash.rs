@@ -0,0 +1,173 @@
+mod common;
+
+use common::Fixture;
+use git_rewrite_lib::{AutoStash, ErrorKind};
+
+#[test]
+fn squashes_consecutive_commits_into_one() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.write("b.txt", "b\n");
+    let b = fixture.commit_as("Alice", "alice@example.com", "B");
+    fixture.write("c.txt", "c\n");
+    let c = fixture.commit_as("Bob", "bob@example.com", "C");
+    fixture.write("a.txt", "a changed\n");
+    let d = fixture.commit_as("Carol", "carol@example.com", "D");
+    fixture.commit_files(&[("e.txt", "e\n")], "E");
+
+    let result = fixture.rewriter().squash(&[b, c, d], "BCD", AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["E", "BCD", "A"]);
+    assert_eq!(result.new_hash, fixture.head().to_string());
+
+    // The squashed commit has the tree of the newest one and the author of the oldest
+    let squashed = fixture.commit_of(fixture.head()).parent(0).unwrap();
+    assert_eq!(squashed.parent_id(0).unwrap(), a);
+    assert_eq!(fixture.files(squashed.id()), fixture.files(d));
+    assert_eq!(squashed.author().name(), Some("Alice"));
+    assert_eq!(squashed.author().when(), fixture.commit_of(b).author().when());
+}
+
+#[test]
+fn squashes_into_the_initial_commit() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.commit_files(&[("c.txt", "c\n")], "C");
+
+    fixture.rewriter().squash(&[a, b], "AB", AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["C", "AB"]);
+    let root = fixture.commit_of(fixture.head()).parent(0).unwrap();
+    assert_eq!(root.parent_count(), 0);
+    assert_eq!(fixture.files(root.id()), fixture.files(b));
+}
+
+#[test]
+fn keeps_renames_and_binary_files() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("old.txt", "content\n")], "A");
+    fixture.rename("old.txt", "new.txt");
+    let b = fixture.commit("Rename");
+    fixture.write("image.bin", [0u8, 159, 146, 150, 0, 255]);
+    let c = fixture.commit("Add binary");
+
+    fixture.rewriter().squash(&[b, c], "Rename and add binary", AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["Rename and add binary", "A"]);
+    let files = fixture.files(fixture.head());
+    assert_eq!(files, fixture.files(c));
+    assert_eq!(files["image.bin"], [0u8, 159, 146, 150, 0, 255]);
+    assert!(!files.contains_key("old.txt"));
+}
+
+#[test]
+fn squashes_below_a_merge() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.branch("feature", c);
+    fixture.checkout("feature");
+    let f = fixture.commit_files(&[("f.txt", "f\n")], "F");
+    fixture.checkout("main");
+    fixture.commit_files(&[("d.txt", "d\n")], "D");
+    fixture.write("f.txt", "f\n");
+    let merge = fixture.merge(f, "Merge feature");
+
+    fixture.rewriter().squash(&[b, c], "BC", AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["Merge feature", "D", "BC", "A"]);
+    let new_merge = fixture.commit_of(fixture.head());
+    assert_eq!(fixture.files(new_merge.id()), fixture.files(merge));
+    let new_f = new_merge.parent(1).unwrap();
+    assert_eq!(new_f.message(), Some("F"));
+    assert_eq!(fixture.message(new_f.parent_id(0).unwrap()), "BC");
+    assert_eq!(fixture.branch_target("feature"), new_f.id());
+}
+
+#[test]
+fn refuses_fewer_than_two_commits() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+
+    let error = fixture.rewriter().squash(&[b], "B", AutoStash::Off).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidRequest));
+    let error = fixture.rewriter().squash(&[], "B", AutoStash::Off).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::InvalidRequest));
+    assert_eq!(fixture.head(), b);
+}
+
+#[test]
+fn refuses_commits_of_diverging_lines() {
+    let mut fixture = Fixture::new();
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    fixture.branch("feature", a);
+    fixture.checkout("feature");
+    let f = fixture.commit_files(&[("f.txt", "f\n")], "F");
+    fixture.checkout("main");
+    fixture.write("f.txt", "f\n");
+    let merge = fixture.merge(f, "Merge feature");
+
+    let error = fixture.rewriter().squash(&[b, f], "BF", AutoStash::Off).unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::InvalidRequest));
+    assert_eq!(fixture.head(), merge);
+}
+
+#[test]
+fn previews_without_moving_refs() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.commit_files(&[("d.txt", "d\n")], "D");
+    let head = fixture.head();
+
+    let preview = fixture.rewriter().preview_squash(&[b, c], "BC").unwrap();
+
+    assert_eq!(preview.affected_refs, ["main"]);
+    assert_eq!(fixture.head(), head);
+    assert_eq!(fixture.log(), ["D", "C", "B", "A"]);
+}
+
+#[test]
+fn moves_refs_on_folded_commits_to_the_squashed_commit() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("a.txt", "a\n")], "A");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+    let c = fixture.commit_files(&[("c.txt", "c\n")], "C");
+    fixture.commit_files(&[("d.txt", "d\n")], "D");
+    fixture.branch("at-b", b);
+    fixture.tag("v-b", b);
+
+    let result = fixture.rewriter().squash(&[b, c], "BC", AutoStash::Off).unwrap();
+
+    assert_eq!(fixture.log(), ["D", "BC", "A"]);
+    let squashed = fixture.commit_of(fixture.head()).parent_id(0).unwrap();
+    assert_eq!(fixture.branch_target("at-b"), squashed);
+    assert_eq!(fixture.tag_target("v-b"), squashed);
+    assert!(fixture.files(squashed).contains_key("b.txt"));
+    assert!(result.updated_branches.contains(&"at-b".to_string()), "{:?}", result.updated_branches);
+}
+
+#[test]
+fn refuses_to_squash_a_commit_a_branch_was_forked_from() {
+    let mut fixture = Fixture::new();
+    fixture.commit_files(&[("base.txt", "base\n")], "Base");
+    let a = fixture.commit_files(&[("a.txt", "a\n")], "A");
+    fixture.branch("feature", a);
+    fixture.checkout("feature");
+    let f = fixture.commit_files(&[("f.txt", "f\n")], "F");
+    fixture.checkout("main");
+    let b = fixture.commit_files(&[("b.txt", "b\n")], "B");
+
+    let error = fixture.rewriter().squash(&[a, b], "AB", AutoStash::Off).unwrap_err();
+
+    assert!(matches!(error.kind, ErrorKind::InvalidRequest), "{:?}", error);
+    assert_eq!(fixture.head(), b);
+    assert_eq!(fixture.branch_target("feature"), f);
+    assert!(fixture.files(f).contains_key("a.txt"));
+}
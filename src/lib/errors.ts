@@ -0,0 +1,11 @@
+import type { RewriteError } from './types';
+
+/** Whether a rejected `invoke` carries a structured command error. */
+export function isRewriteError(e: unknown): e is RewriteError {
+  return typeof e === 'object' && e !== null && 'kind' in e && 'message' in e;
+}
+
+/** Message to show for a rejected `invoke`. */
+export function errorMessage(e: unknown): string {
+  return isRewriteError(e) ? e.message : String(e);
+}
@@ -6,6 +6,17 @@ export interface CommitInfo {
   email: string;
   date: number;
   parent_ids: string[];
+  /** Refs pointing at the commit, like `git log --decorate` */
+  decorations: Decoration[];
+}
+
+export type DecorationKind = 'head' | 'branch' | 'remote_branch' | 'tag' | 'stash';
+
+export interface Decoration {
+  name: string;
+  kind: DecorationKind;
+  /** HEAD itself, or the branch HEAD is on */
+  is_head: boolean;
 }
 
 export interface CommitPage {
@@ -14,6 +25,15 @@ export interface CommitPage {
   total_count: number;
 }
 
+/** Where the commit list starts from; HEAD when no selector is given. */
+export type RefSelector =
+  | { kind: 'head' }
+  | { kind: 'branch'; value: string }
+  | { kind: 'all_branches' }
+  | { kind: 'all_remotes' }
+  | { kind: 'tags' }
+  | { kind: 'glob'; value: string };
+
 export interface RepoInfo {
   name: string;
   path: string;
@@ -61,3 +81,40 @@ export interface CommitDiff {
   stats: DiffStats;
   files: FileDiff[];
 }
+
+/** Outcome of a command rewriting history */
+export interface RewriteResult {
+  new_hash: string;
+  updated_branches: string[];
+  /** Original hash of the commit the rewrite stopped on, null once done */
+  paused_at: string | null;
+  empty_commits: string[];
+  conflicted_commits: string[];
+  /** Why auto-stashed changes could not be restored; they are left in the stash list */
+  stash_warning: string | null;
+}
+
+/** Error returned by every command, tagged by `kind` */
+export type RewriteError = { message: string } & (
+  | { kind: 'dirty_worktree' }
+  | { kind: 'commit_not_in_branch'; branch: string }
+  | { kind: 'merge_commit_unsupported'; commit: string }
+  | { kind: 'conflict'; paths: string[] }
+  | { kind: 'rewrite_in_progress' }
+  | { kind: 'repo_not_found'; path: string }
+  | { kind: 'invalid_request' }
+  | { kind: 'git'; class: string; code: string }
+  | { kind: 'io' }
+  | { kind: 'cancelled' }
+  | { kind: 'internal' }
+);
+
+/** Payload of the `operation-progress` event emitted by long commands */
+export interface OperationProgress {
+  /** Name of the command reporting, e.g. `squash_commits` */
+  operation: string;
+  /** `operation_id` given to the command, if any */
+  operation_id: string | null;
+  done: number;
+  total: number;
+}